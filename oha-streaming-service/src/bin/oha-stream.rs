@@ -0,0 +1,316 @@
+//! Headless runner: executes a test plan in-process, prints live progress to
+//! stderr and exits with a status that reflects the outcome, for CI jobs.
+
+use clap::{Parser, Subcommand};
+use oha_streaming_service::{
+    comparison::{error_rate_percent, Verdict},
+    event_bus::EventBus,
+    load_tester::{LoadTestConfig, LoadTestStatus, TestPhase},
+    plan::{PlanFormat, TestPlan},
+    profile::LoadProfile,
+    report::TestResultsResponse,
+    store::StoredRun,
+    test_run::{TargetSpec, TestRun},
+    StreamMessage,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use uuid::Uuid;
+
+/// The run finished but failed a check: thresholds, error rate or a target
+/// that errored out.
+const EXIT_FAILED: u8 = 1;
+/// The run could not be started: unreadable or invalid plan, or the results
+/// could not be written.
+const EXIT_INVALID: u8 = 2;
+
+#[derive(Parser)]
+#[command(name = "oha-stream", version, about = "Run load test plans without the server")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a test plan and exit non-zero if it fails
+    Run(RunArgs),
+    /// Check a test plan without running it
+    Validate {
+        /// Test plan file (.json, .toml, .yaml or .yml)
+        plan: PathBuf,
+    },
+}
+
+#[derive(clap::Args)]
+struct RunArgs {
+    /// Test plan file (.json, .toml, .yaml or .yml), in the shape
+    /// `POST /api/test/start` accepts
+    plan: PathBuf,
+
+    /// Write the results JSON here instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Fail when any target's error rate exceeds this percentage
+    #[arg(long, value_name = "PERCENT")]
+    max_error_rate: Option<f64>,
+
+    /// Skip the live progress lines
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "oha_streaming_service=warn".into()),
+        )
+        .init();
+
+    let result = match Cli::parse().command {
+        Command::Run(args) => run(args).await,
+        Command::Validate { plan } => validate(&plan),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_INVALID)
+        }
+    }
+}
+
+async fn run(args: RunArgs) -> Result<ExitCode, String> {
+    let (targets, config) = load_plan(&args.plan)?;
+
+    let events = EventBus::new(1000);
+    let mut rx = events.subscribe();
+    let test_run = TestRun::new(Uuid::new_v4().to_string(), &targets, config, events)?;
+
+    let execution = test_run.execute();
+    tokio::pin!(execution);
+    let mut interrupted = false;
+
+    let outcome = loop {
+        tokio::select! {
+            outcome = &mut execution => break outcome,
+            event = rx.recv() => {
+                if let Ok(event) = event {
+                    print_message(&event.message, args.quiet);
+                }
+            }
+            _ = tokio::signal::ctrl_c(), if !interrupted => {
+                eprintln!("Interrupted, stopping targets...");
+                interrupted = true;
+                test_run.clone().stop();
+            }
+        }
+    };
+    while let Ok(event) = rx.try_recv() {
+        print_message(&event.message, args.quiet);
+    }
+
+    let stored = StoredRun::from_run(
+        &test_run,
+        outcome.results,
+        outcome.comparisons,
+        outcome.thresholds,
+    );
+    let response = TestResultsResponse::from_stored(stored);
+    write_results(&response, args.output.as_ref())?;
+
+    let failures = failures(&response, args.max_error_rate);
+    if failures.is_empty() {
+        eprintln!("PASSED");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        for failure in &failures {
+            eprintln!("FAILED: {}", failure);
+        }
+        Ok(ExitCode::from(EXIT_FAILED))
+    }
+}
+
+fn validate(path: &Path) -> Result<ExitCode, String> {
+    let (targets, config) = load_plan(path)?;
+    eprintln!(
+        "{}: OK, {} target(s), {} threshold(s)",
+        path.display(),
+        targets.len(),
+        config.thresholds.len()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Reads, parses and resolves a plan file, in the format its extension names.
+fn load_plan(path: &Path) -> Result<(Vec<TargetSpec>, LoadTestConfig), String> {
+    let format = PlanFormat::from_path(path).ok_or_else(|| {
+        format!(
+            "cannot tell the format of {}; use a .json, .toml, .yaml or .yml file",
+            path.display()
+        )
+    })?;
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+
+    TestPlan::parse(&contents, format)
+        .and_then(TestPlan::resolve)
+        .map_err(|e| format!("invalid plan {}: {}", path.display(), e))
+}
+
+fn write_results(response: &TestResultsResponse, output: Option<&PathBuf>) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(response).map_err(|e| e.to_string())?;
+
+    match output {
+        Some(path) => {
+            fs::write(path, json).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+            eprintln!("Results written to {}", path.display());
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// Reasons the run should count as failed; empty when it passed.
+fn failures(response: &TestResultsResponse, max_error_rate: Option<f64>) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    let aborted = response.thresholds.as_ref().is_some_and(|report| report.aborted);
+    match response.status {
+        LoadTestStatus::Failed => failures.push("a target failed to run".to_string()),
+        LoadTestStatus::Stopped if aborted => failures.push("aborted by a threshold".to_string()),
+        LoadTestStatus::Stopped => failures.push("the run was stopped early".to_string()),
+        _ => {}
+    }
+
+    if let Some(report) = &response.thresholds {
+        for outcome in report.thresholds.iter().filter(|outcome| !outcome.passed) {
+            failures.push(format!("threshold `{}`", outcome.expression));
+        }
+    }
+
+    if let Some(limit) = max_error_rate {
+        for result in &response.results {
+            let rate = error_rate_percent(result);
+            if rate > limit {
+                failures.push(format!(
+                    "{} error rate {:.2}% exceeds {:.2}%",
+                    label(&result.runtime, result.trial),
+                    rate,
+                    limit
+                ));
+            }
+        }
+    }
+
+    failures
+}
+
+fn label(runtime: &str, trial: Option<u32>) -> String {
+    match trial {
+        Some(trial) => format!("{} #{}", runtime, trial),
+        None => runtime.to_string(),
+    }
+}
+
+fn print_message(message: &StreamMessage, quiet: bool) {
+    match message {
+        StreamMessage::TestStarted { test_id, targets, config, .. } if !quiet => {
+            // Stages replace `duration_seconds` when present
+            let duration = LoadProfile::from_config(config).duration_seconds();
+            eprintln!(
+                "Test {}: {} target(s), {}s{}{}",
+                test_id,
+                targets.len(),
+                duration,
+                if config.warmup_seconds > 0 {
+                    format!(" after {}s warm-up", config.warmup_seconds)
+                } else {
+                    String::new()
+                },
+                if config.trials.enabled() {
+                    format!(" x {} trials", config.trials.count)
+                } else {
+                    String::new()
+                }
+            );
+        }
+        StreamMessage::Progress {
+            runtime,
+            trial,
+            phase,
+            requests_sent,
+            errors,
+            current_rps,
+            p95_latency_ms,
+            elapsed_seconds,
+            progress_percent,
+            ..
+        } if !quiet => {
+            let phase = match phase {
+                TestPhase::Warmup => " (warm-up)",
+                TestPhase::Measurement => "",
+            };
+            eprintln!(
+                "[{}]{} {:>6.1}s {:>3.0}% | {} sent, {} errors | {:.1} rps | p95 {:.2}ms",
+                label(runtime, *trial),
+                phase,
+                elapsed_seconds,
+                progress_percent,
+                requests_sent,
+                errors,
+                current_rps,
+                p95_latency_ms
+            );
+        }
+        StreamMessage::TestCompleted { results, .. } => {
+            eprintln!(
+                "[{}] done: {} requests, {:.1} rps, p50 {:.2}ms, p99 {:.2}ms, {:.2}% errors{}",
+                label(&results.runtime, results.trial),
+                results.total_requests,
+                results.requests_per_second,
+                results.p50_latency_ms,
+                results.p99_latency_ms,
+                error_rate_percent(results),
+                if results.late_requests > 0 {
+                    format!(", {} sent late", results.late_requests)
+                } else {
+                    String::new()
+                }
+            );
+        }
+        StreamMessage::TestError { runtime, error, .. } => {
+            eprintln!("[{}] error: {}", runtime, error);
+        }
+        StreamMessage::ThresholdEvaluated { report, .. } => {
+            for outcome in &report.thresholds {
+                let mark = if outcome.passed { "pass" } else { "FAIL" };
+                eprintln!("threshold {} `{}`", mark, outcome.expression);
+            }
+        }
+        StreamMessage::ComparisonCompleted { comparisons, .. } => {
+            for pair in comparisons {
+                let verdict = match pair.verdict {
+                    Verdict::SignificantlyFaster => "significantly faster",
+                    Verdict::SignificantlySlower => "significantly slower",
+                    Verdict::NoSignificantDifference => "no significant difference",
+                };
+                match pair.p50_diff_percent {
+                    Some(p50) => eprintln!(
+                        "{} vs {}: p50 {:+.1}% [{:+.1}, {:+.1}], {}",
+                        pair.target, pair.baseline, p50.value, p50.ci_low, p50.ci_high, verdict
+                    ),
+                    None => eprintln!("{} vs {}: {}", pair.target, pair.baseline, verdict),
+                }
+            }
+        }
+        _ => {}
+    }
+}
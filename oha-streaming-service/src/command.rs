@@ -0,0 +1,113 @@
+use crate::{error::ApiError, subscription::Subscription};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A command a client sends over `/ws`, e.g.
+/// `{"type": "Stop", "id": 7, "test_id": "..."}`.
+#[derive(Debug, Deserialize)]
+pub struct CommandRequest {
+    /// Any JSON value the client picks; echoed in the reply so it can match
+    /// replies to requests.
+    #[serde(default)]
+    pub id: Option<Value>,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum Command {
+    /// Starts a test plan, given in the JSON shape `POST /api/test/start`
+    /// accepts.
+    Start { plan: Value },
+    /// Stops a whole test, or one of its targets.
+    Stop {
+        test_id: String,
+        #[serde(default)]
+        target: Option<String>,
+    },
+    Pause { test_id: String },
+    Resume { test_id: String },
+    /// Status of every active test.
+    List,
+    Subscribe {
+        #[serde(flatten)]
+        subscription: Subscription,
+    },
+    Unsubscribe {
+        #[serde(default)]
+        test_id: Option<String>,
+    },
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Start { .. } => "Start",
+            Self::Stop { .. } => "Stop",
+            Self::Pause { .. } => "Pause",
+            Self::Resume { .. } => "Resume",
+            Self::List => "List",
+            Self::Subscribe { .. } => "Subscribe",
+            Self::Unsubscribe { .. } => "Unsubscribe",
+        }
+    }
+}
+
+impl CommandRequest {
+    /// Parses a text frame. On failure the error reply still carries the
+    /// frame's `id` when it had one.
+    pub fn parse(text: &str) -> Result<Self, CommandReply> {
+        let value: Value = serde_json::from_str(text).map_err(|e| CommandReply::invalid(None, e))?;
+        let id = value.get("id").cloned();
+        serde_json::from_value(value).map_err(|e| CommandReply::invalid(id, e))
+    }
+}
+
+/// Answer to every `CommandRequest`, sent alongside the stream messages.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum CommandReply {
+    /// The command was carried out; `result` depends on the command.
+    Ack {
+        id: Option<Value>,
+        command: &'static str,
+        result: Value,
+    },
+    Error {
+        id: Option<Value>,
+        /// `None` when the frame was not a valid command.
+        command: Option<&'static str>,
+        error: ApiError,
+    },
+}
+
+impl CommandReply {
+    /// Replies with the outcome of `command`.
+    pub fn new(id: Option<Value>, command: &Command, result: Result<Value, ApiError>) -> Self {
+        match result {
+            Ok(result) => Self::Ack {
+                id,
+                command: command.name(),
+                result,
+            },
+            Err(error) => Self::Error {
+                id,
+                command: Some(command.name()),
+                error,
+            },
+        }
+    }
+
+    fn invalid(id: Option<Value>, error: serde_json::Error) -> Self {
+        Self::Error {
+            id,
+            command: None,
+            error: ApiError::new(
+                axum::http::StatusCode::BAD_REQUEST,
+                "invalid_command",
+                format!("invalid command: {}", error),
+            ),
+        }
+    }
+}
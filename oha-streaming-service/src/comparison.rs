@@ -0,0 +1,225 @@
+use crate::{
+    load_tester::LoadTestResult,
+    stats::{self, diff_percent, Estimate, MannWhitney},
+};
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+
+/// Confidence level of the bootstrap intervals.
+const CONFIDENCE: f64 = 0.95;
+/// Mann-Whitney p-value below which a latency difference is significant.
+const SIGNIFICANCE: f64 = 0.05;
+
+/// Side-by-side summary of a run's targets, each measured against the first
+/// target as the baseline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonSummary {
+    pub baseline: String,
+    /// Target with the lowest median latency among those that succeeded at
+    /// least once.
+    pub fastest_p50: Option<String>,
+    pub highest_throughput: Option<String>,
+    pub lowest_error_rate: Option<String>,
+    pub targets: Vec<TargetComparison>,
+    /// Statistical comparison of every pair of targets.
+    pub pairs: Vec<PairComparison>,
+}
+
+/// One target against the baseline. Differences are percentages of the
+/// baseline value (negative latency and positive throughput are better), or
+/// `None` when the baseline value is zero.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetComparison {
+    pub name: String,
+    pub requests_per_second: f64,
+    pub error_rate_percent: f64,
+    pub rps_diff_percent: Option<f64>,
+    pub avg_latency_diff_percent: Option<f64>,
+    pub p50_latency_diff_percent: Option<f64>,
+    pub p95_latency_diff_percent: Option<f64>,
+    pub p99_latency_diff_percent: Option<f64>,
+    /// Error rate minus the baseline's, in percentage points.
+    pub error_rate_diff_points: f64,
+}
+
+impl ComparisonSummary {
+    /// `None` when there is nothing to compare against. Targets with several
+    /// trials are compared on their means across trials.
+    pub fn from_results(results: &[LoadTestResult], pairs: Vec<PairComparison>) -> Option<Self> {
+        let metrics = TargetMetrics::per_target(results);
+        let baseline = metrics.first()?;
+        if metrics.len() < 2 {
+            return None;
+        }
+
+        let targets = metrics
+            .iter()
+            .map(|target| TargetComparison {
+                name: target.name.clone(),
+                requests_per_second: target.rps,
+                error_rate_percent: target.error_rate_percent,
+                rps_diff_percent: diff_percent(target.rps, baseline.rps),
+                avg_latency_diff_percent: diff_percent(target.avg_us, baseline.avg_us),
+                p50_latency_diff_percent: diff_percent(target.p50_us, baseline.p50_us),
+                p95_latency_diff_percent: diff_percent(target.p95_us, baseline.p95_us),
+                p99_latency_diff_percent: diff_percent(target.p99_us, baseline.p99_us),
+                error_rate_diff_points: target.error_rate_percent - baseline.error_rate_percent,
+            })
+            .collect();
+
+        let succeeded = || metrics.iter().filter(|t| t.successful_requests > 0);
+
+        Some(Self {
+            baseline: baseline.name.clone(),
+            fastest_p50: succeeded()
+                .min_by(|a, b| a.p50_us.total_cmp(&b.p50_us))
+                .map(|t| t.name.clone()),
+            highest_throughput: succeeded()
+                .max_by(|a, b| a.rps.total_cmp(&b.rps))
+                .map(|t| t.name.clone()),
+            lowest_error_rate: metrics
+                .iter()
+                .filter(|t| t.total_requests > 0)
+                .min_by(|a, b| a.error_rate_percent.total_cmp(&b.error_rate_percent))
+                .map(|t| t.name.clone()),
+            targets,
+            pairs,
+        })
+    }
+}
+
+/// The headline numbers of one target, averaged over its trials.
+struct TargetMetrics {
+    name: String,
+    total_requests: u64,
+    successful_requests: u64,
+    rps: f64,
+    error_rate_percent: f64,
+    avg_us: f64,
+    p50_us: f64,
+    p95_us: f64,
+    p99_us: f64,
+}
+
+impl TargetMetrics {
+    /// One entry per target, in order of first appearance.
+    fn per_target(results: &[LoadTestResult]) -> Vec<Self> {
+        let mut names: Vec<&str> = Vec::new();
+        for result in results {
+            if !names.contains(&result.runtime.as_str()) {
+                names.push(&result.runtime);
+            }
+        }
+
+        names
+            .into_iter()
+            .map(|name| {
+                let trials: Vec<&LoadTestResult> = results.iter().filter(|r| r.runtime == name).collect();
+                let mean = |metric: fn(&LoadTestResult) -> f64| {
+                    trials.iter().map(|r| metric(r)).sum::<f64>() / trials.len() as f64
+                };
+
+                Self {
+                    name: name.to_string(),
+                    total_requests: trials.iter().map(|r| r.total_requests).sum(),
+                    successful_requests: trials.iter().map(|r| r.successful_requests).sum(),
+                    rps: mean(|r| r.requests_per_second),
+                    error_rate_percent: mean(error_rate_percent),
+                    avg_us: mean(|r| r.avg_latency_us),
+                    p50_us: mean(|r| r.p50_latency_us as f64),
+                    p95_us: mean(|r| r.p95_latency_us as f64),
+                    p99_us: mean(|r| r.p99_latency_us as f64),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Outcome for `target` against `baseline` in a `PairComparison`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verdict {
+    SignificantlyFaster,
+    SignificantlySlower,
+    NoSignificantDifference,
+}
+
+/// Statistical comparison of `target` against `baseline`. Differences are
+/// percentages of the baseline value with 95% bootstrap confidence
+/// intervals; they are `None` when either side has no data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairComparison {
+    pub baseline: String,
+    pub target: String,
+    pub p50_diff_percent: Option<Estimate>,
+    pub p99_diff_percent: Option<Estimate>,
+    /// Mean of the per-second throughput samples.
+    pub throughput_diff_percent: Option<Estimate>,
+    /// Test of the two success latency distributions; `prob_lower` is the
+    /// chance a target request is faster than a baseline one.
+    pub mann_whitney: Option<MannWhitney>,
+    pub verdict: Verdict,
+}
+
+/// What the statistical comparison needs from one finished target.
+pub struct TargetSamples {
+    pub name: String,
+    /// Success latencies in microseconds.
+    pub latency: Histogram<u64>,
+    /// Requests per second of each full progress interval.
+    pub throughput: Vec<f64>,
+}
+
+/// Compares every pair of targets, earlier targets acting as the baseline.
+/// CPU-bound, so run it off the async runtime.
+pub fn compare_targets(samples: &[TargetSamples]) -> Vec<PairComparison> {
+    let mut pairs = Vec::new();
+
+    for (i, baseline) in samples.iter().enumerate() {
+        for target in &samples[i + 1..] {
+            pairs.push(compare_pair(baseline, target));
+        }
+    }
+
+    pairs
+}
+
+fn compare_pair(baseline: &TargetSamples, target: &TargetSamples) -> PairComparison {
+    let quantiles = stats::quantile_diffs(&baseline.latency, &target.latency, &[0.5, 0.99], CONFIDENCE);
+    let (p50_diff_percent, p99_diff_percent) = match quantiles.as_deref() {
+        Some(&[p50, p99]) => (Some(p50), Some(p99)),
+        _ => (None, None),
+    };
+    // `a` is the target so `prob_lower` reads as "target is faster"
+    let mann_whitney = stats::mann_whitney(&target.latency, &baseline.latency);
+
+    // Significant only when the distributions differ and the median shift
+    // is clear of zero, so the verdict never contradicts the p50 interval
+    let verdict = match (mann_whitney, p50_diff_percent) {
+        (Some(test), Some(p50)) if test.p_value < SIGNIFICANCE && p50.excludes_zero() => {
+            if p50.value < 0.0 {
+                Verdict::SignificantlyFaster
+            } else {
+                Verdict::SignificantlySlower
+            }
+        }
+        _ => Verdict::NoSignificantDifference,
+    };
+
+    PairComparison {
+        baseline: baseline.name.clone(),
+        target: target.name.clone(),
+        p50_diff_percent,
+        p99_diff_percent,
+        throughput_diff_percent: stats::mean_diff(&baseline.throughput, &target.throughput, CONFIDENCE),
+        mann_whitney,
+        verdict,
+    }
+}
+
+pub fn error_rate_percent(result: &LoadTestResult) -> f64 {
+    if result.total_requests == 0 {
+        0.0
+    } else {
+        result.failed_requests as f64 / result.total_requests as f64 * 100.0
+    }
+}
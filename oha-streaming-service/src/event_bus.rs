@@ -0,0 +1,272 @@
+use crate::StreamMessage;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Events kept per test for replay; older ones are dropped first.
+const MAX_EVENTS_PER_TEST: usize = 10_000;
+
+/// A published message with its position in the stream.
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    /// Global sequence number, starting at 1 and increasing by one per
+    /// message across all tests.
+    pub seq: u64,
+    #[serde(flatten)]
+    pub message: StreamMessage,
+}
+
+/// Fans stream messages out to every subscriber and keeps a bounded log of
+/// each test's messages, so clients that join late, reconnect or fall
+/// behind can catch up by sequence number.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+    state: Arc<Mutex<BusState>>,
+}
+
+#[derive(Debug, Default)]
+struct BusState {
+    last_seq: u64,
+    logs: HashMap<String, VecDeque<Event>>,
+}
+
+impl EventBus {
+    /// `capacity` is how many live events a slow subscriber may fall behind
+    /// before it lags.
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self {
+            tx,
+            state: Arc::new(Mutex::new(BusState::default())),
+        }
+    }
+
+    /// Numbers, logs and broadcasts a message.
+    pub fn publish(&self, message: StreamMessage) {
+        // Logging and sending under one lock keeps the log and the live
+        // stream in the same order, which `subscribe_since` relies on
+        let mut state = self.state.lock().unwrap();
+        state.last_seq += 1;
+        let event = Event {
+            seq: state.last_seq,
+            message,
+        };
+
+        if let Some(test_id) = event.message.test_id() {
+            let log = state.logs.entry(test_id.to_string()).or_default();
+            if log.len() == MAX_EVENTS_PER_TEST {
+                log.pop_front();
+            }
+            log.push_back(event.clone());
+        }
+
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+
+    /// Subscribes to live events and returns the logged events after `since`,
+    /// with no gap or overlap between the two.
+    pub fn subscribe_since(&self, since: u64) -> (Vec<Event>, broadcast::Receiver<Event>) {
+        let state = self.state.lock().unwrap();
+        (state.events_after(since), self.tx.subscribe())
+    }
+
+    /// Logged events with a sequence number above `since`, oldest first.
+    pub fn replay(&self, since: u64) -> Vec<Event> {
+        self.state.lock().unwrap().events_after(since)
+    }
+
+    /// Drops the logs of tests for which `keep` is false.
+    pub fn retain_tests(&self, mut keep: impl FnMut(&str) -> bool) {
+        self.state
+            .lock()
+            .unwrap()
+            .logs
+            .retain(|test_id, _| keep(test_id));
+    }
+}
+
+/// What an `EventCursor` hands out next.
+#[derive(Debug)]
+pub enum Delivery {
+    Event(Event),
+    /// A `StreamMessage::Lagged` notice; the replayed events follow it.
+    Lagged(StreamMessage),
+}
+
+/// One subscriber's position in the stream: replays the logged events after
+/// its starting point, then follows the live stream, refilling any gap
+/// from the log when it falls behind. Never yields an event twice.
+pub struct EventCursor {
+    bus: EventBus,
+    rx: broadcast::Receiver<Event>,
+    pending: VecDeque<Event>,
+    last_seq: u64,
+}
+
+impl EventCursor {
+    /// Starts after `since`, or at the live stream when it is `None`.
+    pub fn new(bus: &EventBus, since: Option<u64>) -> Self {
+        let (replay, rx) = match since {
+            Some(since) => bus.subscribe_since(since),
+            None => (Vec::new(), bus.subscribe()),
+        };
+
+        Self {
+            bus: bus.clone(),
+            rx,
+            pending: replay.into(),
+            last_seq: since.unwrap_or(0),
+        }
+    }
+
+    /// Queues everything the log holds after the last delivered event, for
+    /// when nothing more will arrive live.
+    pub fn catch_up(&mut self) {
+        self.pending = self.bus.replay(self.last_seq).into();
+    }
+
+    /// The next event already queued, without waiting for live ones.
+    pub fn next_queued(&mut self) -> Option<Event> {
+        while let Some(event) = self.pending.pop_front() {
+            if event.seq > self.last_seq {
+                self.last_seq = event.seq;
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// The next event; `None` once the bus is gone. Cancel-safe, so it can
+    /// be raced against other futures.
+    pub async fn next(&mut self) -> Option<Delivery> {
+        loop {
+            if let Some(event) = self.next_queued() {
+                return Some(Delivery::Event(event));
+            }
+
+            match self.rx.recv().await {
+                Ok(event) => self.pending.push_back(event),
+                Err(RecvError::Lagged(missed)) => {
+                    let since = self.last_seq;
+                    // A fresh receiver, so the replayed events still queued
+                    // in the old one cannot make it lag again
+                    let (replay, rx) = self.bus.subscribe_since(since);
+                    self.rx = rx;
+                    self.pending = replay.into();
+                    return Some(Delivery::Lagged(StreamMessage::Lagged {
+                        missed,
+                        replayed: self.pending.len(),
+                        since,
+                        timestamp: chrono::Utc::now(),
+                    }));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl BusState {
+    fn events_after(&self, since: u64) -> Vec<Event> {
+        let mut events: Vec<Event> = self
+            .logs
+            .values()
+            .flat_map(|log| {
+                let start = log.partition_point(|event| event.seq <= since);
+                log.range(start..).cloned()
+            })
+            .collect();
+        events.sort_by_key(|event| event.seq);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paused(test_id: &str) -> StreamMessage {
+        StreamMessage::TestPaused {
+            test_id: test_id.to_string(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    fn seq(delivery: Option<Delivery>) -> u64 {
+        match delivery {
+            Some(Delivery::Event(event)) => event.seq,
+            other => panic!("expected an event, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_then_follows_without_gap_or_overlap() {
+        let bus = EventBus::new(16);
+        for test_id in ["a", "b", "a", "b", "a"] {
+            bus.publish(paused(test_id));
+        }
+
+        // Replay interleaves both tests' logs in sequence order
+        let mut cursor = EventCursor::new(&bus, Some(2));
+        bus.publish(paused("b"));
+        for expected in 3..=6 {
+            assert_eq!(seq(cursor.next().await), expected);
+        }
+        assert!(cursor.next_queued().is_none());
+    }
+
+    #[tokio::test]
+    async fn live_cursor_skips_the_log() {
+        let bus = EventBus::new(16);
+        bus.publish(paused("a"));
+
+        let mut cursor = EventCursor::new(&bus, None);
+        bus.publish(paused("a"));
+        assert_eq!(seq(cursor.next().await), 2);
+    }
+
+    #[tokio::test]
+    async fn recovers_from_lag_through_the_log() {
+        let bus = EventBus::new(2);
+        let mut cursor = EventCursor::new(&bus, None);
+        for _ in 0..5 {
+            bus.publish(paused("a"));
+        }
+
+        match cursor.next().await {
+            Some(Delivery::Lagged(StreamMessage::Lagged {
+                missed, replayed, since, ..
+            })) => assert_eq!((missed, replayed, since), (3, 5, 0)),
+            other => panic!("expected a lag notice, got {:?}", other),
+        }
+        for expected in 1..=5 {
+            assert_eq!(seq(cursor.next().await), expected);
+        }
+
+        // Fills the channel again without lagging: the replayed events are
+        // not left queued in it
+        bus.publish(paused("a"));
+        bus.publish(paused("a"));
+        assert_eq!(seq(cursor.next().await), 6);
+        assert_eq!(seq(cursor.next().await), 7);
+    }
+
+    #[test]
+    fn keeps_only_retained_logs() {
+        let bus = EventBus::new(16);
+        bus.publish(paused("a"));
+        bus.publish(paused("b"));
+        bus.retain_tests(|test_id| test_id == "b");
+
+        let replayed: Vec<u64> = bus.replay(0).iter().map(|event| event.seq).collect();
+        assert_eq!(replayed, [2]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Load-testing engine shared by the streaming server and the `oha-stream`
+//! command-line runner.
+
+use serde::Serialize;
+
+pub mod command;
+pub mod comparison;
+pub mod error;
+pub mod event_bus;
+pub mod load_tester;
+pub mod metrics;
+pub mod pacer;
+pub mod pause;
+pub mod plan;
+pub mod profile;
+pub mod report;
+pub mod request;
+pub mod scenario;
+pub mod stats;
+pub mod store;
+pub mod subscription;
+pub mod test_run;
+pub mod threshold;
+pub mod timed_client;
+pub mod trial;
+use comparison::PairComparison;
+use load_tester::{LoadTestConfig, LoadTestResult, TestPhase};
+use metrics::{IntervalMetrics, PhaseSummary};
+use test_run::{Annotation, TargetSpec};
+use threshold::ThresholdReport;
+
+// WebSocket message types
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum StreamMessage {
+    TestStarted {
+        test_id: String,
+        targets: Vec<TargetSpec>,
+        config: LoadTestConfig,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    Progress {
+        test_id: String,
+        runtime: String,
+        trial: Option<u32>,
+        phase: TestPhase,
+        requests_sent: u64,
+        responses_received: u64,
+        errors: u64,
+        current_rps: f64,
+        avg_latency_ms: f64,
+        p95_latency_ms: f64,
+        avg_latency_us: f64,
+        p95_latency_us: u64,
+        phases: Option<PhaseSummary>,
+        elapsed_seconds: f64,
+        progress_percent: f64,
+        current_stage: Option<usize>,
+        stage_name: Option<String>,
+        target_rps: f64,
+        target_connections: u64,
+        interval: IntervalMetrics,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    TestCompleted {
+        test_id: String,
+        runtime: String,
+        results: LoadTestResult,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    TestError {
+        test_id: String,
+        runtime: String,
+        error: String,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    ComparisonCompleted {
+        test_id: String,
+        comparisons: Vec<PairComparison>,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    ThresholdEvaluated {
+        test_id: String,
+        #[serde(flatten)]
+        report: ThresholdReport,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    /// Every target of the test has been parked.
+    TestPaused {
+        test_id: String,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    TestResumed {
+        test_id: String,
+        /// How long this pause lasted.
+        paused_seconds: f64,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    /// The rate or connection count was changed while the test ran.
+    Annotation {
+        test_id: String,
+        #[serde(flatten)]
+        annotation: Annotation,
+    },
+    /// Sent to one subscriber that fell behind the live stream instead of
+    /// the `missed` messages it lost; the `replayed` ones still in the log
+    /// follow it. Never logged and not subject to subscriptions.
+    Lagged {
+        missed: u64,
+        replayed: usize,
+        /// Sequence number of the last message delivered before the lag.
+        since: u64,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+}
+
+impl StreamMessage {
+    /// Every `type` a subscription can filter on.
+    pub const TYPES: [&'static str; 9] = [
+        "TestStarted",
+        "Progress",
+        "TestCompleted",
+        "TestError",
+        "ComparisonCompleted",
+        "ThresholdEvaluated",
+        "TestPaused",
+        "TestResumed",
+        "Annotation",
+    ];
+
+    /// The `type` tag this message is serialized with.
+    pub fn message_type(&self) -> &'static str {
+        match self {
+            Self::TestStarted { .. } => "TestStarted",
+            Self::Progress { .. } => "Progress",
+            Self::TestCompleted { .. } => "TestCompleted",
+            Self::TestError { .. } => "TestError",
+            Self::ComparisonCompleted { .. } => "ComparisonCompleted",
+            Self::ThresholdEvaluated { .. } => "ThresholdEvaluated",
+            Self::TestPaused { .. } => "TestPaused",
+            Self::TestResumed { .. } => "TestResumed",
+            Self::Annotation { .. } => "Annotation",
+            Self::Lagged { .. } => "Lagged",
+        }
+    }
+
+    /// The test a message is about; `None` for connection notices.
+    pub fn test_id(&self) -> Option<&str> {
+        match self {
+            Self::TestStarted { test_id, .. }
+            | Self::Progress { test_id, .. }
+            | Self::TestCompleted { test_id, .. }
+            | Self::TestError { test_id, .. }
+            | Self::ComparisonCompleted { test_id, .. }
+            | Self::ThresholdEvaluated { test_id, .. }
+            | Self::TestPaused { test_id, .. }
+            | Self::TestResumed { test_id, .. }
+            | Self::Annotation { test_id, .. } => Some(test_id),
+            Self::Lagged { .. } => None,
+        }
+    }
+
+    /// The target a message is about; `None` for messages about the whole
+    /// test.
+    pub fn target(&self) -> Option<&str> {
+        match self {
+            Self::Progress { runtime, .. }
+            | Self::TestCompleted { runtime, .. }
+            | Self::TestError { runtime, .. } => Some(runtime),
+            Self::TestStarted { .. }
+            | Self::ComparisonCompleted { .. }
+            | Self::ThresholdEvaluated { .. }
+            | Self::TestPaused { .. }
+            | Self::TestResumed { .. }
+            | Self::Annotation { .. }
+            | Self::Lagged { .. } => None,
+        }
+    }
+}
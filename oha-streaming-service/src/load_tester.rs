@@ -1,4 +1,19 @@
-use crate::StreamMessage;
+use crate::{
+    event_bus::EventBus,
+    metrics::{
+        us_to_ms, EndpointResult, EndpointStats, HistogramConfig, IntervalMetrics, LatencySummary,
+        PhaseHistograms, PhaseSummary, StatusClass, StatusClassHistograms,
+    },
+    pacer::Pacer,
+    pause::PauseSwitch,
+    profile::{LoadProfile, Stage},
+    request::RequestSpec,
+    scenario::{RequestTemplate, Scenario},
+    threshold::Threshold,
+    timed_client::TimedClient,
+    trial::TrialConfig,
+    StreamMessage,
+};
 use chrono::{DateTime, Utc};
 use hdrhistogram::Histogram;
 use reqwest::Client;
@@ -11,19 +26,80 @@ use std::{
     },
     time::{Duration, Instant},
 };
-use tokio::{sync::broadcast, time::interval};
-use tracing::{debug, info};
+use tokio::{task::JoinSet, time::interval};
+use tracing::{debug, error, info};
+
+/// How often a worker outside the current stage's connection count checks
+/// whether it has been brought back into the pool.
+const IDLE_WORKER_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Most open-loop requests one target may have in flight. Slots that come
+/// due while the limit is reached wait for a response and go out late.
+const MAX_OPEN_LOOP_IN_FLIGHT: usize = 10_000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoadTestConfig {
     pub duration_seconds: u64,
     pub connections: u64,
     pub rate_per_second: Option<u64>,
+    #[serde(default)]
+    pub mode: LoadMode,
+    /// Optional multi-stage profile; when present it replaces the flat
+    /// `duration_seconds`, `connections` and `rate_per_second` schedule.
+    #[serde(default)]
+    pub stages: Vec<Stage>,
+    /// Method, headers, query and body to send; defaults to
+    /// `RequestSpec::for_url` for the target.
+    #[serde(default)]
+    pub request: Option<RequestSpec>,
+    /// Weighted traffic mix; when empty every request uses `request`.
+    #[serde(default)]
+    pub scenario: Vec<RequestTemplate>,
+    #[serde(default)]
+    pub histogram: HistogramConfig,
+    /// Send through the instrumented client and record DNS, connect, TLS,
+    /// time-to-first-byte and body download times separately.
+    #[serde(default)]
+    pub phase_timing: bool,
+    /// Repeat every target and aggregate across the repetitions.
+    #[serde(default)]
+    pub trials: TrialConfig,
+    /// Traffic sent at the initial rate and connection count before the
+    /// measured run, so JIT warm-up does not skew the results. Nothing sent
+    /// during warm-up is recorded.
+    #[serde(default)]
+    pub warmup_seconds: u64,
+    /// Pass/fail assertions on the results.
+    #[serde(default)]
+    pub thresholds: Vec<Threshold>,
+}
+
+/// How requests are paced against the target.
+///
+/// `ClosedLoop` waits for each response before the worker sends its next
+/// request, so a slow server is offered less load. `OpenLoop` sends requests
+/// on a fixed global schedule regardless of how many are still in flight and
+/// measures latency from the intended send time, which corrects for
+/// coordinated omission.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoadMode {
+    #[default]
+    ClosedLoop,
+    OpenLoop,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Which part of a test a progress update belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestPhase {
+    Warmup,
+    Measurement,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoadTestResult {
     pub runtime: String,
+    /// Trial number, starting at 1, when the run repeats its targets.
+    #[serde(default)]
+    pub trial: Option<u32>,
     pub total_requests: u64,
     pub successful_requests: u64,
     pub failed_requests: u64,
@@ -35,12 +111,40 @@ pub struct LoadTestResult {
     pub p50_latency_ms: f64,
     pub p95_latency_ms: f64,
     pub p99_latency_ms: f64,
+    pub avg_latency_us: f64,
+    pub min_latency_us: u64,
+    pub max_latency_us: u64,
+    pub p50_latency_us: u64,
+    pub p95_latency_us: u64,
+    pub p99_latency_us: u64,
+    /// Open-loop requests that went out after their scheduled time because
+    /// `MAX_OPEN_LOOP_IN_FLIGHT` requests were already waiting for a
+    /// response. Their latency still counts from the scheduled time.
+    #[serde(default)]
+    pub late_requests: u64,
     pub error_types: std::collections::HashMap<String, u64>,
+    pub endpoints: std::collections::HashMap<String, EndpointResult>,
+    pub latency_by_status_class: std::collections::HashMap<StatusClass, LatencySummary>,
+    pub phases: Option<PhaseSummary>,
+}
+
+/// Rate and connection count set while the test runs, which take the place
+/// of the planned ones, and the pacer of the phase in progress that has to
+/// follow them.
+#[derive(Debug, Default)]
+pub struct LiveConfig {
+    pub rate_per_second: Option<u64>,
+    pub connections: Option<u64>,
+    pacer: Option<Arc<Pacer>>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LoadTestStatus {
+    /// Waiting for an earlier trial to finish.
+    Pending,
     Running,
+    /// Running, but parked by a pause until resumed.
+    Paused,
     Completed,
     Failed,
     Stopped,
@@ -51,22 +155,43 @@ pub struct LoadTest {
     pub test_id: String,
     pub runtime: String,
     pub target_url: String,
+    pub trial: Option<u32>,
     pub config: LoadTestConfig,
-    pub started_at: DateTime<Utc>,
+    pub profile: LoadProfile,
+    /// When the test started running; its creation time while pending.
+    pub started_at: Arc<Mutex<DateTime<Utc>>>,
+    /// `pause.paused_time()` when `started_at` was set, so elapsed time can
+    /// leave out the pauses since.
+    pub paused_before_start: Arc<Mutex<Duration>>,
     
     // Statistics
     pub requests_sent: Arc<AtomicU64>,
     pub responses_received: Arc<AtomicU64>,
     pub errors: Arc<AtomicU64>,
+    pub late_requests: Arc<AtomicU64>,
+    /// Latency of successful responses, in microseconds.
     pub latency_histogram: Arc<Mutex<Histogram<u64>>>,
+    /// Same as `latency_histogram`, but reset on every progress tick.
+    pub interval_histogram: Arc<Mutex<Histogram<u64>>>,
+    /// Latency of every request, failed ones included, by status class.
+    pub status_class_histograms: Arc<Mutex<StatusClassHistograms>>,
+    /// Per-phase timings, only populated when `phase_timing` is enabled.
+    pub phase_histograms: Arc<Mutex<PhaseHistograms>>,
     pub error_types: Arc<Mutex<std::collections::HashMap<String, u64>>>,
+    pub endpoint_stats: Arc<Mutex<std::collections::HashMap<String, EndpointStats>>>,
+    /// Requests per second of each full progress interval.
+    pub throughput_samples: Arc<Mutex<Vec<f64>>>,
+    /// Final result, set once the test has finished or failed.
+    pub result: Arc<Mutex<Option<LoadTestResult>>>,
     
     // Control
     pub should_stop: Arc<AtomicBool>,
     pub status: Arc<Mutex<LoadTestStatus>>,
+    pub pause: PauseSwitch,
+    pub live: Arc<Mutex<LiveConfig>>,
     
     // Communication
-    pub broadcast_tx: broadcast::Sender<StreamMessage>,
+    pub events: EventBus,
 }
 
 impl LoadTest {
@@ -74,154 +199,396 @@ impl LoadTest {
         test_id: String,
         runtime: String,
         target_url: String,
+        trial: Option<u32>,
         config: LoadTestConfig,
-        broadcast_tx: broadcast::Sender<StreamMessage>,
+        events: EventBus,
     ) -> Self {
+        let histogram_config = config.histogram;
+
         Self {
             test_id,
             runtime,
             target_url,
+            trial,
+            profile: LoadProfile::from_config(&config),
             config,
-            started_at: Utc::now(),
+            started_at: Arc::new(Mutex::new(Utc::now())),
+            paused_before_start: Arc::new(Mutex::new(Duration::ZERO)),
             requests_sent: Arc::new(AtomicU64::new(0)),
             responses_received: Arc::new(AtomicU64::new(0)),
             errors: Arc::new(AtomicU64::new(0)),
-            latency_histogram: Arc::new(Mutex::new(
-                Histogram::new_with_bounds(1, 60_000, 3).unwrap()
-            )),
+            late_requests: Arc::new(AtomicU64::new(0)),
+            latency_histogram: Arc::new(Mutex::new(histogram_config.new_histogram())),
+            interval_histogram: Arc::new(Mutex::new(histogram_config.new_histogram())),
+            status_class_histograms: Arc::new(Mutex::new(StatusClassHistograms::new(histogram_config))),
+            phase_histograms: Arc::new(Mutex::new(PhaseHistograms::new(histogram_config))),
             error_types: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            endpoint_stats: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            throughput_samples: Arc::new(Mutex::new(Vec::new())),
+            result: Arc::new(Mutex::new(None)),
             should_stop: Arc::new(AtomicBool::new(false)),
-            status: Arc::new(Mutex::new(LoadTestStatus::Running)),
-            broadcast_tx,
+            status: Arc::new(Mutex::new(LoadTestStatus::Pending)),
+            pause: PauseSwitch::new(),
+            live: Arc::new(Mutex::new(LiveConfig::default())),
+            events,
         }
     }
 
     pub fn status(&self) -> LoadTestStatus {
-        self.status.lock().unwrap().clone()
+        match self.status.lock().unwrap().clone() {
+            LoadTestStatus::Running if self.pause.is_paused() => LoadTestStatus::Paused,
+            status => status,
+        }
+    }
+
+    pub fn result(&self) -> Option<LoadTestResult> {
+        self.result.lock().unwrap().clone()
     }
 
     pub fn started_at(&self) -> DateTime<Utc> {
-        self.started_at
+        *self.started_at.lock().unwrap()
     }
 
     pub fn elapsed_seconds(&self) -> f64 {
-        (Utc::now() - self.started_at).num_milliseconds() as f64 / 1000.0
+        match self.status() {
+            LoadTestStatus::Pending => 0.0,
+            _ => self.active_time().as_secs_f64(),
+        }
+    }
+
+    /// Time since `started_at`, not counting time spent paused.
+    fn active_time(&self) -> Duration {
+        let paused = self
+            .pause
+            .paused_time()
+            .saturating_sub(*self.paused_before_start.lock().unwrap());
+        (Utc::now() - self.started_at())
+            .to_std()
+            .unwrap_or_default()
+            .saturating_sub(paused)
+    }
+
+    fn mark_started(&self) {
+        *self.started_at.lock().unwrap() = Utc::now();
+        *self.paused_before_start.lock().unwrap() = self.pause.paused_time();
+    }
+
+    /// Results so far, as if the test ended now.
+    pub fn snapshot(&self) -> LoadTestResult {
+        self.build_result(self.active_time())
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        self.should_stop.load(Ordering::Relaxed)
     }
 
     pub fn stop(&mut self) {
         self.should_stop.store(true, Ordering::Relaxed);
         *self.status.lock().unwrap() = LoadTestStatus::Stopped;
+        // Workers waiting for a slot, parked or not, have to wake up to see
+        // the stop
+        if let Some(pacer) = &self.live.lock().unwrap().pacer {
+            pacer.stop();
+        }
+        self.pause.resume();
+    }
+
+    /// Holds the rate and/or connection count at the given values from now
+    /// on, for the rest of this phase and any phase still to come.
+    pub fn reconfigure(&self, rate: Option<u64>, connections: Option<u64>) {
+        let mut live = self.live.lock().unwrap();
+        live.rate_per_second = rate.or(live.rate_per_second);
+        live.connections = connections.or(live.connections);
+        if let Some(pacer) = &live.pacer {
+            pacer.retune(rate, connections);
+        }
     }
 
     pub async fn run(&self) -> LoadTestResult {
         info!("Starting load test for {} runtime: {}", self.runtime, self.target_url);
+        self.mark_started();
+        *self.status.lock().unwrap() = LoadTestStatus::Running;
+
+        let client = if self.config.phase_timing {
+            HttpClient::Timed(TimedClient::new(
+                Duration::from_secs(30),
+                self.profile.max_connections() as usize,
+            ))
+        } else {
+            match Client::builder()
+                .timeout(Duration::from_secs(30))
+                .pool_idle_timeout(Duration::from_secs(90))
+                .pool_max_idle_per_host(self.profile.max_connections() as usize)
+                .build()
+            {
+                Ok(client) => HttpClient::Standard(client),
+                Err(e) => return self.fail(format!("Failed to build HTTP client: {}", e)),
+            }
+        };
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .pool_idle_timeout(Duration::from_secs(90))
-            .pool_max_idle_per_host(self.config.connections as usize)
-            .build()
-            .unwrap();
-
-        let test_start = Instant::now();
-        let test_duration = Duration::from_secs(self.config.duration_seconds);
+        let scenario = match Scenario::prepare(&self.config, &self.target_url) {
+            Ok(scenario) => Arc::new(scenario),
+            Err(error) => return self.fail(error),
+        };
 
-        // Start progress reporting task
-        let progress_task = self.start_progress_reporting();
-
-        // Calculate request timing
-        let requests_per_second = self.config.rate_per_second
-            .unwrap_or(self.config.connections * 10);
-        let request_interval = Duration::from_millis(1000 / requests_per_second.max(1));
-
-        // Start worker tasks
-        let mut worker_handles = Vec::new();
-        
-        for worker_id in 0..self.config.connections {
-            let worker = LoadTestWorker {
-                worker_id,
-                client: client.clone(),
-                target_url: self.target_url.clone(),
-                request_interval,
-                requests_sent: self.requests_sent.clone(),
-                responses_received: self.responses_received.clone(),
-                errors: self.errors.clone(),
-                latency_histogram: self.latency_histogram.clone(),
-                error_types: self.error_types.clone(),
-                should_stop: self.should_stop.clone(),
-            };
+        if self.config.warmup_seconds > 0 && !self.stop_requested() {
+            info!("Warming up {} for {}s", self.runtime, self.config.warmup_seconds);
+            self.warmup()
+                .generate_load(client.clone(), scenario.clone(), TestPhase::Warmup)
+                .await;
 
-            let handle = tokio::spawn(async move {
-                worker.run(test_duration).await;
-            });
+            // Measurement starts from zero once warm-up is over
+            self.mark_started();
+        }
 
-            worker_handles.push(handle);
+        // Register every endpoint up front so unused ones still show in results
+        {
+            let mut endpoint_stats = self.endpoint_stats.lock().unwrap();
+            for endpoint in scenario.endpoints() {
+                endpoint_stats
+                    .entry(endpoint.name.clone())
+                    .or_insert_with(|| EndpointStats::new(self.config.histogram));
+            }
         }
 
-        // Wait for test completion or stop signal
-        for handle in worker_handles {
-            let _ = handle.await;
+        let elapsed = self.generate_load(client, scenario, TestPhase::Measurement).await;
+        let result = self.build_result(elapsed);
+
+        // Publish the result before the status so a finished test always has one
+        *self.result.lock().unwrap() = Some(result.clone());
+        *self.status.lock().unwrap() = if self.stop_requested() {
+            LoadTestStatus::Stopped
+        } else {
+            LoadTestStatus::Completed
+        };
+
+        // Broadcast completion
+        let completion_message = StreamMessage::TestCompleted {
+            test_id: self.test_id.clone(),
+            runtime: self.runtime.clone(),
+            results: result.clone(),
+            timestamp: Utc::now(),
+        };
+
+        self.events.publish(completion_message);
+
+        info!("Load test completed for {}: {} requests in {:.2}s ({:.2} RPS)",
+            self.runtime, result.total_requests, elapsed.as_secs_f64(), result.requests_per_second);
+
+        result
+    }
+
+    /// Sends traffic following `self.profile`, with any live changes applied,
+    /// until it ends or the test is stopped, reporting progress for `phase`.
+    /// Returns how long it ran.
+    async fn generate_load(
+        &self,
+        client: HttpClient,
+        scenario: Arc<Scenario>,
+        phase: TestPhase,
+    ) -> Duration {
+        // All workers draw from one schedule so the combined rate matches the config
+        let pacer = {
+            let mut live = self.live.lock().unwrap();
+            let mut profile = self.profile.clone();
+            profile.override_from(0.0, live.rate_per_second, live.connections);
+
+            let pacer = Arc::new(Pacer::new(Instant::now(), profile, self.config.mode, self.pause.clone()));
+            live.pacer = Some(pacer.clone());
+            pacer
+        };
+
+        // Start progress reporting task
+        let progress_task = self.start_progress_reporting(phase, pacer.clone());
+
+        match self.config.mode {
+            LoadMode::ClosedLoop => {
+                let mut workers = JoinSet::new();
+                let mut spawned = 0;
+                let mut retuned = pacer.subscribe();
+
+                // Workers above the current stage's connection count stay idle;
+                // retuning to more connections than ever before adds workers
+                loop {
+                    let pool_size = pacer.profile().max_connections();
+                    for worker_id in spawned..pool_size {
+                        let worker = self.worker(worker_id, client.clone(), scenario.clone(), pacer.clone());
+                        workers.spawn(async move {
+                            worker.run().await;
+                        });
+                    }
+                    spawned = spawned.max(pool_size);
+
+                    // Wait for test completion or stop signal
+                    tokio::select! {
+                        finished = workers.join_next() => {
+                            if finished.is_none() {
+                                break;
+                            }
+                        }
+                        _ = retuned.changed() => {}
+                    }
+                }
+            }
+            LoadMode::OpenLoop => {
+                let worker = Arc::new(self.worker(0, client, scenario, pacer.clone()));
+                worker.run_open_loop().await;
+            }
         }
 
         // Stop progress reporting
         progress_task.abort();
+        self.live.lock().unwrap().pacer = None;
+
+        pacer.elapsed()
+    }
+
+    /// A throwaway copy of this test that runs the warm-up: the initial rate
+    /// and connection count for `warmup_seconds`, recorded into its own
+    /// statistics. It shares the stop flag, status, pause and live changes
+    /// with this test.
+    fn warmup(&self) -> LoadTest {
+        let config = LoadTestConfig {
+            duration_seconds: self.config.warmup_seconds,
+            connections: self.profile.connections_at(0.0),
+            rate_per_second: Some((self.profile.rate_at(0.0).round() as u64).max(1)),
+            stages: Vec::new(),
+            warmup_seconds: 0,
+            ..self.config.clone()
+        };
 
-        let elapsed = test_start.elapsed();
-        *self.status.lock().unwrap() = LoadTestStatus::Completed;
+        LoadTest {
+            should_stop: self.should_stop.clone(),
+            status: self.status.clone(),
+            pause: self.pause.clone(),
+            live: self.live.clone(),
+            ..LoadTest::new(
+                self.test_id.clone(),
+                self.runtime.clone(),
+                self.target_url.clone(),
+                self.trial,
+                config,
+                self.events.clone(),
+            )
+        }
+    }
 
-        // Calculate final results
+    /// Marks the test as failed before any traffic was sent.
+    fn fail(&self, error: String) -> LoadTestResult {
+        error!("Load test for {} failed: {}", self.runtime, error);
+        let result = self.build_result(Duration::ZERO);
+        *self.result.lock().unwrap() = Some(result.clone());
+        *self.status.lock().unwrap() = LoadTestStatus::Failed;
+
+        self.events.publish(StreamMessage::TestError {
+            test_id: self.test_id.clone(),
+            runtime: self.runtime.clone(),
+            error,
+            timestamp: Utc::now(),
+        });
+
+        result
+    }
+
+    fn build_result(&self, elapsed: Duration) -> LoadTestResult {
         let total_requests = self.requests_sent.load(Ordering::Relaxed);
         let successful_requests = self.responses_received.load(Ordering::Relaxed);
         let failed_requests = self.errors.load(Ordering::Relaxed);
 
-        let histogram = self.latency_histogram.lock().unwrap();
-        
-        let result = LoadTestResult {
+        let latency = LatencySummary::from_histogram(&self.latency_histogram.lock().unwrap());
+
+        LoadTestResult {
             runtime: self.runtime.clone(),
+            trial: self.trial,
             total_requests,
             successful_requests,
             failed_requests,
             total_duration_seconds: elapsed.as_secs_f64(),
-            requests_per_second: total_requests as f64 / elapsed.as_secs_f64(),
-            avg_latency_ms: histogram.mean(),
-            min_latency_ms: histogram.min() as f64,
-            max_latency_ms: histogram.max() as f64,
-            p50_latency_ms: histogram.value_at_quantile(0.5) as f64,
-            p95_latency_ms: histogram.value_at_quantile(0.95) as f64,
-            p99_latency_ms: histogram.value_at_quantile(0.99) as f64,
+            requests_per_second: total_requests as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            avg_latency_ms: latency.avg_latency_ms,
+            min_latency_ms: latency.min_latency_ms,
+            max_latency_ms: latency.max_latency_ms,
+            p50_latency_ms: latency.p50_latency_ms,
+            p95_latency_ms: latency.p95_latency_ms,
+            p99_latency_ms: latency.p99_latency_ms,
+            avg_latency_us: latency.avg_latency_us,
+            min_latency_us: latency.min_latency_us,
+            max_latency_us: latency.max_latency_us,
+            p50_latency_us: latency.p50_latency_us,
+            p95_latency_us: latency.p95_latency_us,
+            p99_latency_us: latency.p99_latency_us,
+            late_requests: self.late_requests.load(Ordering::Relaxed),
             error_types: self.error_types.lock().unwrap().clone(),
-        };
-
-        // Broadcast completion
-        let completion_message = StreamMessage::TestCompleted {
-            test_id: self.test_id.clone(),
-            runtime: self.runtime.clone(),
-            results: result.clone(),
-            timestamp: Utc::now(),
-        };
-
-        let _ = self.broadcast_tx.send(completion_message);
+            endpoints: self.endpoint_stats
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, stats)| (name.clone(), stats.result(elapsed.as_secs_f64())))
+                .collect(),
+            latency_by_status_class: self.status_class_histograms.lock().unwrap().summaries(),
+            phases: self.phase_summary(),
+        }
+    }
 
-        info!("Load test completed for {}: {} requests in {:.2}s ({:.2} RPS)",
-            self.runtime, total_requests, elapsed.as_secs_f64(), result.requests_per_second);
+    fn phase_summary(&self) -> Option<PhaseSummary> {
+        self.config
+            .phase_timing
+            .then(|| self.phase_histograms.lock().unwrap().summary())
+    }
 
-        result
+    fn worker(
+        &self,
+        worker_id: u64,
+        client: HttpClient,
+        scenario: Arc<Scenario>,
+        pacer: Arc<Pacer>,
+    ) -> LoadTestWorker {
+        LoadTestWorker {
+            worker_id,
+            client,
+            target_url: self.target_url.clone(),
+            scenario,
+            pacer,
+            requests_sent: self.requests_sent.clone(),
+            responses_received: self.responses_received.clone(),
+            errors: self.errors.clone(),
+            late_requests: self.late_requests.clone(),
+            latency_histogram: self.latency_histogram.clone(),
+            interval_histogram: self.interval_histogram.clone(),
+            status_class_histograms: self.status_class_histograms.clone(),
+            phase_histograms: self.phase_histograms.clone(),
+            error_types: self.error_types.clone(),
+            endpoint_stats: self.endpoint_stats.clone(),
+            histogram_config: self.config.histogram,
+            should_stop: self.should_stop.clone(),
+        }
     }
 
-    fn start_progress_reporting(&self) -> tokio::task::JoinHandle<()> {
+    /// Publishes `Progress` every second, skipping the ticks that fall in a
+    /// pause and leaving paused time out of the interval metrics.
+    fn start_progress_reporting(&self, phase: TestPhase, pacer: Arc<Pacer>) -> tokio::task::JoinHandle<()> {
         let test_id = self.test_id.clone();
         let runtime = self.runtime.clone();
-        let started_at = self.started_at;
+        let pause = self.pause.clone();
+        let trial = self.trial;
         let requests_sent = self.requests_sent.clone();
         let responses_received = self.responses_received.clone();
         let errors = self.errors.clone();
         let latency_histogram = self.latency_histogram.clone();
+        let interval_histogram = self.interval_histogram.clone();
+        let throughput_samples = self.throughput_samples.clone();
+        let phase_histograms = self.config.phase_timing.then(|| self.phase_histograms.clone());
         let should_stop = self.should_stop.clone();
-        let broadcast_tx = self.broadcast_tx.clone();
-        let duration = self.config.duration_seconds;
+        let events = self.events.clone();
+        let staged = !self.config.stages.is_empty();
+        let duration = pacer.profile().duration_seconds();
 
         tokio::spawn(async move {
             let mut interval = interval(Duration::from_secs(1));
+            let mut last_tick = Instant::now();
+            let mut last_paused = pause.paused_time();
+            let mut last_requests = 0;
+            let mut last_errors = 0;
             
             loop {
                 interval.tick().await;
@@ -229,9 +596,23 @@ impl LoadTest {
                 if should_stop.load(Ordering::Relaxed) {
                     break;
                 }
+                if pause.is_paused() {
+                    continue;
+                }
+
+                let paused = pause.paused_time();
+                let interval_seconds = last_tick
+                    .elapsed()
+                    .saturating_sub(paused.saturating_sub(last_paused))
+                    .as_secs_f64();
+                last_tick = Instant::now();
+                last_paused = paused;
 
-                let elapsed = (Utc::now() - started_at).num_milliseconds() as f64 / 1000.0;
-                let progress_percent = (elapsed / duration as f64 * 100.0).min(100.0);
+                let elapsed = pacer.elapsed_seconds();
+                // Retuning can change the profile between ticks
+                let profile = pacer.profile().clone();
+                let progress_percent = (elapsed / duration.max(f64::EPSILON) * 100.0).min(100.0);
+                let current_stage = staged.then(|| profile.stage_at(elapsed));
 
                 let requests = requests_sent.load(Ordering::Relaxed);
                 let responses = responses_received.load(Ordering::Relaxed);
@@ -239,29 +620,60 @@ impl LoadTest {
 
                 let current_rps = requests as f64 / elapsed.max(0.1);
 
-                let (avg_latency, p95_latency) = {
+                let (avg_latency_us, p95_latency_us) = {
                     let histogram = latency_histogram.lock().unwrap();
-                    (histogram.mean(), histogram.value_at_quantile(0.95) as f64)
+                    (histogram.mean(), histogram.value_at_quantile(0.95))
+                };
+
+                let interval_metrics = {
+                    let mut histogram = interval_histogram.lock().unwrap();
+                    let metrics = IntervalMetrics::new(
+                        interval_seconds,
+                        requests - last_requests,
+                        error_count - last_errors,
+                        &histogram,
+                    );
+                    histogram.reset();
+                    metrics
                 };
+                last_requests = requests;
+                last_errors = error_count;
+
+                // The first tick fires immediately and covers no real interval
+                if interval_seconds >= 0.5 {
+                    throughput_samples.lock().unwrap().push(interval_metrics.rps);
+                }
 
                 let progress_message = StreamMessage::Progress {
                     test_id: test_id.clone(),
                     runtime: runtime.clone(),
+                    trial,
+                    phase,
                     requests_sent: requests,
                     responses_received: responses,
                     errors: error_count,
                     current_rps,
-                    avg_latency_ms: avg_latency,
-                    p95_latency_ms: p95_latency,
+                    avg_latency_ms: us_to_ms(avg_latency_us),
+                    p95_latency_ms: us_to_ms(p95_latency_us as f64),
+                    avg_latency_us,
+                    p95_latency_us,
+                    phases: phase_histograms
+                        .as_ref()
+                        .map(|histograms| histograms.lock().unwrap().summary()),
                     elapsed_seconds: elapsed,
                     progress_percent,
+                    current_stage,
+                    stage_name: current_stage.and_then(|stage| profile.stage_name(stage)),
+                    target_rps: profile.rate_at(elapsed),
+                    target_connections: profile.connections_at(elapsed),
+                    interval: interval_metrics,
                     timestamp: Utc::now(),
                 };
 
-                let _ = broadcast_tx.send(progress_message);
+                events.publish(progress_message);
 
                 // Stop reporting if test duration exceeded
-                if elapsed >= duration as f64 {
+                if elapsed >= duration {
                     break;
                 }
             }
@@ -269,134 +681,192 @@ impl LoadTest {
     }
 }
 
+/// HTTP client a worker sends with: `reqwest` normally, or the timed client
+/// when per-phase timings were requested.
+#[derive(Clone)]
+enum HttpClient {
+    Standard(Client),
+    Timed(TimedClient),
+}
+
 struct LoadTestWorker {
     worker_id: u64,
-    client: Client,
+    client: HttpClient,
     target_url: String,
-    request_interval: Duration,
+    scenario: Arc<Scenario>,
+    pacer: Arc<Pacer>,
     requests_sent: Arc<AtomicU64>,
     responses_received: Arc<AtomicU64>,
     errors: Arc<AtomicU64>,
+    late_requests: Arc<AtomicU64>,
     latency_histogram: Arc<Mutex<Histogram<u64>>>,
+    interval_histogram: Arc<Mutex<Histogram<u64>>>,
+    status_class_histograms: Arc<Mutex<StatusClassHistograms>>,
+    phase_histograms: Arc<Mutex<PhaseHistograms>>,
     error_types: Arc<Mutex<std::collections::HashMap<String, u64>>>,
+    endpoint_stats: Arc<Mutex<std::collections::HashMap<String, EndpointStats>>>,
+    histogram_config: HistogramConfig,
     should_stop: Arc<AtomicBool>,
 }
 
 impl LoadTestWorker {
-    async fn run(&self, test_duration: Duration) {
-        let start_time = Instant::now();
-        let mut last_request = Instant::now();
-
+    async fn run(&self) {
         debug!("Worker {} starting for URL: {}", self.worker_id, self.target_url);
 
-        while start_time.elapsed() < test_duration && !self.should_stop.load(Ordering::Relaxed) {
-            // Rate limiting
-            let time_since_last = last_request.elapsed();
-            if time_since_last < self.request_interval {
-                tokio::time::sleep(self.request_interval - time_since_last).await;
+        while !self.should_stop.load(Ordering::Relaxed) {
+            let elapsed = self.pacer.elapsed_seconds();
+            if elapsed >= self.pacer.profile().duration_seconds() {
+                break;
             }
-            last_request = Instant::now();
-
-            // Make request
-            let request_start = Instant::now();
-            self.requests_sent.fetch_add(1, Ordering::Relaxed);
-
-            // Determine request method and payload based on endpoint
-            let response = if self.target_url.contains("/api/system/stress-test") {
-                self.client.post(&self.target_url)
-                    .json(&serde_json::json!({
-                        "duration": 5000,
-                        "intensity": 5,
-                        "memoryIntensive": true
-                    }))
-                    .send()
-                    .await
-            } else if self.target_url.contains("/api/system/heap-dump") {
-                self.client.post(&self.target_url)
-                    .json(&serde_json::json!({}))
-                    .send()
-                    .await
-            } else if self.target_url.contains("/api/system/memory-stress") {
-                self.client.post(&self.target_url)
-                    .json(&serde_json::json!({
-                        "objectCount": 5000,
-                        "objectSize": 500,
-                        "duration": 10000
-                    }))
-                    .send()
-                    .await
-            } else {
-                self.client.get(&self.target_url).send().await
+
+            if self.worker_id >= self.pacer.profile().connections_at(elapsed) {
+                tokio::time::sleep(IDLE_WORKER_POLL_INTERVAL).await;
+                continue;
+            }
+
+            if self.pacer.wait().await.is_none() || self.should_stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            self.send_request(Instant::now()).await;
+        }
+
+        debug!("Worker {} completed", self.worker_id);
+    }
+
+    /// Drives requests on the pacer's schedule, spawning each one independently
+    /// so the send rate does not depend on how quickly responses come back.
+    async fn run_open_loop(self: Arc<Self>) {
+        let mut in_flight = JoinSet::new();
+        let mut sequence: u64 = 0;
+
+        debug!("Open-loop scheduler starting for URL: {}", self.target_url);
+
+        while !self.should_stop.load(Ordering::Relaxed) {
+            let Some(scheduled_at) = self.pacer.wait().await else {
+                break;
             };
 
-            match response {
-                Ok(response) => {
-                    let latency = request_start.elapsed();
-                    let latency_ms = latency.as_millis() as u64;
-
-                    if response.status().is_success() {
-                        self.responses_received.fetch_add(1, Ordering::Relaxed);
-                        
-                        // Record latency
-                        if let Ok(mut histogram) = self.latency_histogram.lock() {
-                            let _ = histogram.record(latency_ms);
-                        }
-                    } else {
-                        self.errors.fetch_add(1, Ordering::Relaxed);
+            // Reap finished requests so the set does not grow unbounded
+            while in_flight.try_join_next().is_some() {}
+
+            // At the limit the slot waits for room and goes out late
+            if in_flight.len() >= MAX_OPEN_LOOP_IN_FLIGHT {
+                self.late_requests.fetch_add(1, Ordering::Relaxed);
+                in_flight.join_next().await;
+            }
+            if self.should_stop.load(Ordering::Relaxed) {
+                break;
+            }
+            sequence += 1;
+
+            let worker = self.clone();
+            in_flight.spawn(async move {
+                worker.send_request(scheduled_at).await;
+            });
+        }
+
+        while in_flight.join_next().await.is_some() {}
+
+        debug!("Open-loop scheduler completed after {} requests", sequence);
+    }
+
+    /// Sends a single request and records its outcome. Latency is measured
+    /// from `scheduled_at`, the time the request was meant to go out.
+    async fn send_request(&self, scheduled_at: Instant) {
+        let endpoint = self.scenario.pick();
+        self.requests_sent.fetch_add(1, Ordering::Relaxed);
+
+        let outcome = match &self.client {
+            HttpClient::Standard(client) => {
+                // Read the body so latency runs to its last byte, as with
+                // the timed client
+                let response = match endpoint.request.send(client).await {
+                    Ok(response) => {
                         let status = response.status();
-                        let error_detail = match status.as_u16() {
-                            400 => "HTTP_400_Bad_Request",
-                            401 => "HTTP_401_Unauthorized",
-                            403 => "HTTP_403_Forbidden",
-                            404 => "HTTP_404_Not_Found",
-                            429 => "HTTP_429_Too_Many_Requests",
-                            500 => "HTTP_500_Internal_Server_Error",
-                            502 => "HTTP_502_Bad_Gateway",
-                            503 => "HTTP_503_Service_Unavailable",
-                            504 => "HTTP_504_Gateway_Timeout",
-                            _ => &format!("HTTP_{}_{}",
-                                status.as_u16(),
-                                status.canonical_reason().unwrap_or("Unknown")
-                            ),
-                        };
-                        self.record_error(error_detail);
+                        response.bytes().await.map(|_| status)
+                    }
+                    Err(e) => Err(e),
+                };
+                response.map_err(|e| {
+                    let error_type = describe_error(&e);
+                    // Log detailed error for debugging
+                    debug!("Worker {} error: {} - Full error: {:?}", self.worker_id, error_type, e);
+                    error_type
+                })
+            }
+            HttpClient::Timed(client) => match client.send(&endpoint.request).await {
+                Ok(response) => {
+                    if let Ok(mut histograms) = self.phase_histograms.lock() {
+                        histograms.record(&response.phases);
                     }
+                    Ok(response.status)
                 }
                 Err(e) => {
-                    self.errors.fetch_add(1, Ordering::Relaxed);
+                    debug!("Worker {} error: {:?}", self.worker_id, e);
+                    Err(e.to_string())
+                }
+            },
+        };
+
+        match outcome {
+            Ok(status) => {
+                let latency_us = scheduled_at.elapsed().as_micros() as u64;
+
+                self.record_latency(&endpoint.name, StatusClass::from_status(status), latency_us);
+
+                if status.is_success() {
+                    self.responses_received.fetch_add(1, Ordering::Relaxed);
                     
-                    let error_type = if e.is_timeout() {
-                        "Timeout".to_string()
-                    } else if e.is_connect() {
-                        // Try to get more specific connection error info
-                        if let Some(source) = e.source() {
-                            format!("Connection: {}", source)
-                        } else {
-                            "Connection: Failed to establish connection".to_string()
-                        }
-                    } else if e.is_request() {
-                        format!("Request: {}", e)
-                    } else if e.is_body() {
-                        "Body: Failed to read response body".to_string()
-                    } else if e.is_decode() {
-                        "Decode: Failed to decode response".to_string()
-                    } else if e.is_redirect() {
-                        "Redirect: Too many redirects".to_string()
-                    } else if e.is_builder() {
-                        "Builder: Invalid request".to_string()
-                    } else {
-                        format!("Unknown: {}", e)
+                    // Record latency
+                    if let Ok(mut histogram) = self.latency_histogram.lock() {
+                        histogram.saturating_record(latency_us);
+                    }
+                    if let Ok(mut histogram) = self.interval_histogram.lock() {
+                        histogram.saturating_record(latency_us);
+                    }
+                } else {
+                    self.errors.fetch_add(1, Ordering::Relaxed);
+                    let error_detail = match status.as_u16() {
+                        400 => "HTTP_400_Bad_Request",
+                        401 => "HTTP_401_Unauthorized",
+                        403 => "HTTP_403_Forbidden",
+                        404 => "HTTP_404_Not_Found",
+                        429 => "HTTP_429_Too_Many_Requests",
+                        500 => "HTTP_500_Internal_Server_Error",
+                        502 => "HTTP_502_Bad_Gateway",
+                        503 => "HTTP_503_Service_Unavailable",
+                        504 => "HTTP_504_Gateway_Timeout",
+                        _ => &format!("HTTP_{}_{}",
+                            status.as_u16(),
+                            status.canonical_reason().unwrap_or("Unknown")
+                        ),
                     };
-                    
-                    self.record_error(&error_type);
-                    
-                    // Log detailed error for debugging
-                    debug!("Worker {} error: {} - Full error: {:?}", self.worker_id, error_type, e);
+                    self.record_error(error_detail);
                 }
             }
+            Err(error_type) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                let latency_us = scheduled_at.elapsed().as_micros() as u64;
+                self.record_latency(&endpoint.name, StatusClass::TransportError, latency_us);
+                self.record_error(&error_type);
+            }
         }
+    }
 
-        debug!("Worker {} completed", self.worker_id);
+    /// Records a request's latency against its endpoint and status class,
+    /// whatever the outcome.
+    fn record_latency(&self, endpoint: &str, class: StatusClass, latency_us: u64) {
+        if let Ok(mut endpoint_stats) = self.endpoint_stats.lock() {
+            endpoint_stats
+                .entry(endpoint.to_string())
+                .or_insert_with(|| EndpointStats::new(self.histogram_config))
+                .record(class, latency_us);
+        }
+
+        if let Ok(mut histograms) = self.status_class_histograms.lock() {
+            histograms.record(class, latency_us);
+        }
     }
 
     fn record_error(&self, error_type: &str) {
@@ -405,3 +875,28 @@ impl LoadTestWorker {
         }
     }
 }
+
+fn describe_error(e: &reqwest::Error) -> String {
+    if e.is_timeout() {
+        "Timeout".to_string()
+    } else if e.is_connect() {
+        // Try to get more specific connection error info
+        if let Some(source) = e.source() {
+            format!("Connection: {}", source)
+        } else {
+            "Connection: Failed to establish connection".to_string()
+        }
+    } else if e.is_request() {
+        format!("Request: {}", e)
+    } else if e.is_body() {
+        "Body: Failed to read response body".to_string()
+    } else if e.is_decode() {
+        "Decode: Failed to decode response".to_string()
+    } else if e.is_redirect() {
+        "Redirect: Too many redirects".to_string()
+    } else if e.is_builder() {
+        "Builder: Invalid request".to_string()
+    } else {
+        format!("Unknown: {}", e)
+    }
+}
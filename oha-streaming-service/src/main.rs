@@ -1,79 +1,55 @@
 use axum::{
-    extract::{Path, State, WebSocketUpgrade},
-    http::Method,
-    response::{IntoResponse, Json},
-    routing::{get, post},
+    extract::{
+        rejection::{QueryRejection, StringRejection},
+        ws::{rejection::WebSocketUpgradeRejection, Message, WebSocket},
+        Path, Query, State, WebSocketUpgrade,
+    },
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{get, patch, post},
     Router,
 };
+use futures_util::stream::{self, SplitSink, Stream};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
+    convert::Infallible,
     net::SocketAddr,
     sync::{Arc, Mutex},
     time::Duration,
 };
-use tokio::{sync::broadcast, time::interval};
+use tokio::{
+    sync::mpsc,
+    time::{interval, Interval},
+};
 use tower_http::cors::{Any, CorsLayer};
 use tracing::{info, warn, error};
-use tracing_subscriber;
 use uuid::Uuid;
 
-mod load_tester;
-use load_tester::{LoadTest, LoadTestConfig, LoadTestResult, LoadTestStatus};
+use oha_streaming_service::{
+    error::ApiError,
+    load_tester::{LoadMode, LoadTestStatus},
+    plan::{PlanChange, PlanError, PlanFormat, TestPlan},
+    report::TestResultsResponse,
+    store::{HistoryPage, HistoryQuery, ResultStore, StoredRun},
+    command::{Command, CommandReply, CommandRequest},
+    event_bus::{Delivery, EventBus, EventCursor},
+    subscription::{Subscription, SubscriptionQuery, Subscriptions},
+    test_run::TestRun,
+};
 
 // Application state
 #[derive(Clone)]
 pub struct AppState {
-    pub active_tests: Arc<Mutex<HashMap<String, LoadTest>>>,
-    pub broadcast_tx: broadcast::Sender<StreamMessage>,
-}
-
-// WebSocket message types
-#[derive(Debug, Clone, Serialize)]
-#[serde(tag = "type")]
-pub enum StreamMessage {
-    TestStarted {
-        test_id: String,
-        config: LoadTestConfig,
-        timestamp: chrono::DateTime<chrono::Utc>,
-    },
-    Progress {
-        test_id: String,
-        runtime: String,
-        requests_sent: u64,
-        responses_received: u64,
-        errors: u64,
-        current_rps: f64,
-        avg_latency_ms: f64,
-        p95_latency_ms: f64,
-        elapsed_seconds: f64,
-        progress_percent: f64,
-        timestamp: chrono::DateTime<chrono::Utc>,
-    },
-    TestCompleted {
-        test_id: String,
-        runtime: String,
-        results: LoadTestResult,
-        timestamp: chrono::DateTime<chrono::Utc>,
-    },
-    TestError {
-        test_id: String,
-        runtime: String,
-        error: String,
-        timestamp: chrono::DateTime<chrono::Utc>,
-    },
+    pub active_tests: Arc<Mutex<HashMap<String, TestRun>>>,
+    pub events: EventBus,
+    pub store: Arc<ResultStore>,
 }
 
 // HTTP API types
-#[derive(Debug, Deserialize)]
-pub struct StartTestRequest {
-    pub node_url: String,
-    pub bun_url: String,
-    pub duration_seconds: Option<u64>,
-    pub connections: Option<u64>,
-    pub rate_per_second: Option<u64>,
-}
-
 #[derive(Debug, Serialize)]
 pub struct StartTestResponse {
     pub test_id: String,
@@ -86,6 +62,21 @@ pub struct TestStatusResponse {
     pub status: LoadTestStatus,
     pub started_at: chrono::DateTime<chrono::Utc>,
     pub elapsed_seconds: f64,
+    pub targets: Vec<TargetStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TargetStatus {
+    pub name: String,
+    pub url: String,
+    pub trial: Option<u32>,
+    pub status: LoadTestStatus,
+    pub elapsed_seconds: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StopTestQuery {
+    pub target: Option<String>,
 }
 
 #[tokio::main]
@@ -98,13 +89,17 @@ async fn main() {
         )
         .init();
 
-    // Create broadcast channel for WebSocket messages
-    let (broadcast_tx, _) = broadcast::channel(1000);
+    // Create the event bus that numbers, logs and fans out stream messages
+    let events = EventBus::new(1000);
+
+    // Open the store that keeps completed runs after cleanup
+    let store = ResultStore::open().expect("Failed to open result store");
 
     // Create application state
     let state = AppState {
         active_tests: Arc::new(Mutex::new(HashMap::new())),
-        broadcast_tx: broadcast_tx.clone(),
+        events,
+        store: Arc::new(store),
     };
 
     // Start background task to clean up completed tests
@@ -123,12 +118,21 @@ async fn main() {
         .route("/health", get(health))
         .route("/api/test/start", post(start_test))
         .route("/api/test/status/:test_id", get(get_test_status))
+        .route("/api/test/results/:test_id", get(get_test_results))
         .route("/api/test/stop/:test_id", post(stop_test))
+        .route("/api/test/:test_id", patch(reconfigure_test))
+        .route("/api/test/:test_id/pause", post(pause_test))
+        .route("/api/test/:test_id/resume", post(resume_test))
+        .route("/api/test/:test_id/events", get(test_events))
+        .route("/api/tests", get(list_tests))
+        .route("/api/tests/:test_id/results", get(get_stored_results))
         .route("/ws", get(websocket_handler))
+        .method_not_allowed_fallback(method_not_allowed)
+        .fallback(route_not_found)
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
-                .allow_methods([Method::GET, Method::POST])
+                .allow_methods([Method::GET, Method::POST, Method::PATCH])
                 .allow_headers(Any),
         )
         .with_state(state);
@@ -161,168 +165,474 @@ async fn health() -> Json<serde_json::Value> {
     }))
 }
 
+/// Accepts the plan as JSON, TOML or YAML, chosen by `Content-Type`; JSON
+/// when the header is missing.
 async fn start_test(
     State(state): State<AppState>,
-    Json(request): Json<StartTestRequest>,
-) -> Result<Json<StartTestResponse>, axum::http::StatusCode> {
+    headers: HeaderMap,
+    body: Result<String, StringRejection>,
+) -> Result<Json<StartTestResponse>, ApiError> {
+    let format = match headers.get(header::CONTENT_TYPE) {
+        None => PlanFormat::Json,
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(PlanFormat::from_content_type)
+            .ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "unsupported_media_type",
+                    "test plans must be sent as application/json, application/toml or application/yaml",
+                )
+            })?,
+    };
+    let plan = TestPlan::parse(&body?, format).map_err(reject_plan)?;
+
+    launch_test(&state, plan).map(Json)
+}
+
+/// Validates and starts a plan in the background; shared by
+/// `POST /api/test/start` and the WebSocket `Start` command.
+fn launch_test(state: &AppState, plan: TestPlan) -> Result<StartTestResponse, ApiError> {
+    let (targets, config) = plan.resolve().map_err(reject_plan)?;
+
     let test_id = Uuid::new_v4().to_string();
-    
-    info!("Starting new load test: {}", test_id);
 
-    let config = LoadTestConfig {
-        duration_seconds: request.duration_seconds.unwrap_or(60),
-        connections: request.connections.unwrap_or(10),
-        rate_per_second: request.rate_per_second,
-    };
+    info!("Starting new load test: {} ({} targets)", test_id, targets.len());
 
-    // Start both tests
-    let test_id_clone = test_id.clone();
-    let config_clone = config.clone();
-    let node_url = request.node_url;
-    let bun_url = request.bun_url;
-    let broadcast_tx = state.broadcast_tx.clone();
-    let active_tests = state.active_tests.clone();
-    
+    // Create and register one load test per target before any of them start
+    let run = TestRun::new(test_id.clone(), &targets, config, state.events.clone())
+        .map_err(|message| reject_plan(PlanError { field: None, message }))?;
+    state.active_tests.lock().unwrap().insert(test_id.clone(), run.clone());
+
+    // Run every target, then compare and persist the results
+    let store = state.store.clone();
     tokio::spawn(async move {
-        // Create load tests for both runtimes
-        let node_test = LoadTest::new(
-            test_id_clone.clone(),
-            "node".to_string(),
-            node_url,
-            config_clone.clone(),
-            broadcast_tx.clone(),
-        );
-
-        let bun_test = LoadTest::new(
-            test_id_clone.clone(),
-            "bun".to_string(), 
-            bun_url,
-            config_clone.clone(),
-            broadcast_tx.clone(),
-        );
-
-        // Store tests
-        {
-            let mut tests = active_tests.lock().unwrap();
-            tests.insert(format!("{}-node", test_id_clone), node_test.clone());
-            tests.insert(format!("{}-bun", test_id_clone), bun_test.clone());
+        let outcome = run.execute().await;
+
+        let stored = StoredRun::from_run(&run, outcome.results, outcome.comparisons.clone(), outcome.thresholds);
+        match tokio::task::spawn_blocking(move || store.save(&stored)).await {
+            Ok(Ok(())) => info!("Saved results for test {}", run.test_id),
+            Ok(Err(e)) => error!("Failed to save results for test {}: {}", run.test_id, e),
+            Err(e) => error!("Result save task for test {} panicked: {}", run.test_id, e),
         }
-        
-        let node_handle = tokio::spawn(async move { node_test.run().await });
-        let bun_handle = tokio::spawn(async move { bun_test.run().await });
 
-        // Wait for both to complete
-        let _ = tokio::join!(node_handle, bun_handle);
+        // Only now is the run finished, so cleanup never drops an unsaved run
+        run.finish(outcome.comparisons);
     });
 
-    // Broadcast test started message
-    let start_message = StreamMessage::TestStarted {
-        test_id: test_id.clone(),
-        config,
-        timestamp: chrono::Utc::now(),
-    };
-
-    let _ = state.broadcast_tx.send(start_message);
-
-    Ok(Json(StartTestResponse {
+    Ok(StartTestResponse {
         test_id,
         message: "Load test started successfully".to_string(),
-    }))
+    })
+}
+
+fn reject_plan(error: PlanError) -> ApiError {
+    warn!("Rejecting test: {}", error);
+    error.into()
 }
 
 async fn get_test_status(
     Path(test_id): Path<String>,
     State(state): State<AppState>,
-) -> Result<Json<TestStatusResponse>, axum::http::StatusCode> {
+) -> Result<Json<TestStatusResponse>, ApiError> {
     let tests = state.active_tests.lock().unwrap();
     
-    // Look for either node or bun variant of the test
-    let test_key = format!("{}-node", test_id);
-    if let Some(test) = tests.get(&test_key) {
-        Ok(Json(TestStatusResponse {
-            test_id,
-            status: test.status(),
-            started_at: test.started_at(),
-            elapsed_seconds: test.elapsed_seconds(),
-        }))
+    if let Some(run) = tests.get(&test_id) {
+        Ok(Json(status_response(run)))
     } else {
-        Err(axum::http::StatusCode::NOT_FOUND)
+        Err(test_not_found(&test_id))
     }
 }
 
+fn test_not_found(test_id: &str) -> ApiError {
+    ApiError::not_found(format!("no active test {}", test_id))
+}
+
+fn status_response(run: &TestRun) -> TestStatusResponse {
+    TestStatusResponse {
+        test_id: run.test_id.clone(),
+        status: run.status(),
+        started_at: run.started_at,
+        elapsed_seconds: run.elapsed_seconds(),
+        targets: run
+            .targets
+            .iter()
+            .map(|test| TargetStatus {
+                name: test.runtime.clone(),
+                url: test.target_url.clone(),
+                trial: test.trial,
+                status: test.status(),
+                elapsed_seconds: test.elapsed_seconds(),
+            })
+            .collect(),
+    }
+}
+
+/// Final results of every target and trial. Answers 202 with the current
+/// status while any target is still running or being analyzed, and falls back to the result store once the
+/// run has been cleaned up.
+async fn get_test_results(
+    Path(test_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Response, ApiError> {
+    let active = state.active_tests.lock().unwrap().get(&test_id).map(|run| {
+        match TestResultsResponse::from_run(run) {
+            Some(response) => Json(response).into_response(),
+            None => (StatusCode::ACCEPTED, Json(status_response(run))).into_response(),
+        }
+    });
+    if let Some(response) = active {
+        return Ok(response);
+    }
+
+    let stored = load_stored(&state, &test_id).await?;
+    Ok(Json(TestResultsResponse::from_stored(stored)).into_response())
+}
+
 async fn stop_test(
     Path(test_id): Path<String>,
+    query: Result<Query<StopTestQuery>, QueryRejection>,
     State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, axum::http::StatusCode> {
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let Query(query) = query?;
+    stop_run(&state, &test_id, query.target.as_deref()).map(Json)
+}
+
+/// Stops a single target (every trial of it) when one is named, otherwise
+/// the whole run; shared by `POST /api/test/stop` and the `Stop` command.
+fn stop_run(state: &AppState, test_id: &str, target: Option<&str>) -> Result<serde_json::Value, ApiError> {
     let mut tests = state.active_tests.lock().unwrap();
-    
-    // Stop both node and bun tests
-    let node_key = format!("{}-node", test_id);
-    let bun_key = format!("{}-bun", test_id);
-    
-    let mut stopped = false;
-    
-    if let Some(test) = tests.get_mut(&node_key) {
-        test.stop();
-        stopped = true;
+    let run = tests
+        .get_mut(test_id)
+        .ok_or_else(|| test_not_found(test_id))?;
+
+    match target {
+        Some(target) => {
+            if !run.stop_target(target) {
+                return Err(ApiError::not_found(format!("test {} has no target `{}`", test_id, target))
+                    .with_field("target"));
+            }
+        }
+        None => run.stop(),
     }
-    
-    if let Some(test) = tests.get_mut(&bun_key) {
-        test.stop();
-        stopped = true;
+
+    Ok(serde_json::json!({
+        "message": "Test stopped successfully",
+        "test_id": test_id
+    }))
+}
+
+/// Changes the rate and/or connection count of a running test from now on,
+/// e.g. `{"rate_per_second": 800, "note": "looking for the knee"}`, and
+/// answers with the annotation it added to the timeline.
+async fn reconfigure_test(
+    Path(test_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<String, StringRejection>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let is_json = headers.get(header::CONTENT_TYPE).is_none_or(|value| {
+        value.to_str().ok().and_then(PlanFormat::from_content_type) == Some(PlanFormat::Json)
+    });
+    if !is_json {
+        return Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            "changes must be sent as application/json",
+        ));
+    }
+    let change = PlanChange::parse(&body?)?;
+
+    let tests = state.active_tests.lock().unwrap();
+    let run = tests.get(&test_id).ok_or_else(|| test_not_found(&test_id))?;
+    // Open-loop requests are not tied to a connection count
+    if run.config.mode == LoadMode::OpenLoop && change.connections.is_some() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "invalid_state",
+            "open-loop tests have no connection count to change; change `rate_per_second` instead",
+        )
+        .with_field("connections"));
     }
+    let annotation = run
+        .reconfigure(change)
+        .map_err(|e| ApiError::new(StatusCode::CONFLICT, "invalid_state", e))?;
+
+    Ok(Json(serde_json::json!({
+        "message": "Test reconfigured successfully",
+        "test_id": test_id,
+        "annotation": annotation
+    })))
+}
+
+async fn pause_test(
+    Path(test_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    pause_run(&state, &test_id, true).map(Json)
+}
+
+async fn resume_test(
+    Path(test_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    pause_run(&state, &test_id, false).map(Json)
+}
 
-    if stopped {
-        Ok(Json(serde_json::json!({
-            "message": "Test stopped successfully",
-            "test_id": test_id
-        })))
+/// Pauses or resumes a whole run; shared by `POST /api/test/:id/pause`,
+/// `POST /api/test/:id/resume` and the `Pause` and `Resume` commands.
+fn pause_run(state: &AppState, test_id: &str, pause: bool) -> Result<serde_json::Value, ApiError> {
+    let tests = state.active_tests.lock().unwrap();
+    let run = tests.get(test_id).ok_or_else(|| test_not_found(test_id))?;
+
+    let (result, message) = if pause {
+        (run.pause(), "Test paused successfully")
     } else {
-        Err(axum::http::StatusCode::NOT_FOUND)
+        (run.resume(), "Test resumed successfully")
+    };
+    result.map_err(|e| ApiError::new(StatusCode::CONFLICT, "invalid_state", e))?;
+
+    Ok(serde_json::json!({
+        "message": message,
+        "test_id": test_id
+    }))
+}
+
+async fn list_tests(
+    query: Result<Query<HistoryQuery>, QueryRejection>,
+    State(state): State<AppState>,
+) -> Result<Json<HistoryPage>, ApiError> {
+    let Query(query) = query?;
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return Err(ApiError::invalid_query("`from` must be earlier than `to`").with_field("from"));
+        }
+    }
+
+    Ok(Json(state.store.list(&query)))
+}
+
+async fn get_stored_results(
+    Path(test_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<StoredRun>, ApiError> {
+    load_stored(&state, &test_id).await.map(Json)
+}
+
+/// Reads a saved run off the async runtime; 404 when it was never saved.
+async fn load_stored(state: &AppState, test_id: &str) -> Result<StoredRun, ApiError> {
+    let store = state.store.clone();
+    let id = test_id.to_string();
+    tokio::task::spawn_blocking(move || store.get(&id))
+        .await
+        .map_err(|e| ApiError::internal(format!("result store task failed: {}", e)))?
+        .map_err(|e| {
+            error!("Failed to read stored results: {}", e);
+            ApiError::internal("failed to read stored results")
+        })?
+        .ok_or_else(|| ApiError::not_found(format!("no stored results for test {}", test_id)))
+}
+
+async fn route_not_found() -> ApiError {
+    ApiError::not_found("no such endpoint")
+}
+
+async fn method_not_allowed(method: Method, uri: Uri) -> ApiError {
+    ApiError::new(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "method_not_allowed",
+        format!("{} is not supported on {}", method, uri.path()),
+    )
+}
+
+/// Streams one test's messages as Server-Sent Events, for clients that cannot
+/// use `/ws`. Takes the same `?target=`, `?types=` and `?since=` filters;
+/// a reconnecting client's `Last-Event-ID` takes the place of `since`. The
+/// stream ends once the test has finished and its last message was sent.
+async fn test_events(
+    Path(test_id): Path<String>,
+    query: Result<Query<SubscriptionQuery>, QueryRejection>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Query(query) = query?;
+    let since = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| {
+                    ApiError::invalid_query("Last-Event-ID must be a sequence number")
+                        .with_field("Last-Event-ID")
+                })?,
+        ),
+        None => query.since,
+    };
+    let mut subscription = query.into_subscription();
+    subscription.test_id = Some(test_id.clone());
+    subscription
+        .validate()
+        .map_err(|e| ApiError::invalid_query(e).with_field("types"))?;
+
+    if !state.active_tests.lock().unwrap().contains_key(&test_id) {
+        return Err(test_not_found(&test_id));
+    }
+
+    let stream = EventStream {
+        cursor: EventCursor::new(&state.events, since),
+        subscription,
+        state,
+        test_id,
+        finish_check: interval(Duration::from_secs(1)),
+        finished: false,
+    };
+    let sse = Sse::new(stream.into_stream()).keep_alive(KeepAlive::new().text("keep-alive"));
+
+    // Ask buffering reverse proxies such as nginx to pass events through
+    Ok(([(header::HeaderName::from_static("x-accel-buffering"), "no")], sse))
+}
+
+/// State of one `/api/test/:test_id/events` response.
+struct EventStream {
+    cursor: EventCursor,
+    subscription: Subscription,
+    state: AppState,
+    test_id: String,
+    finish_check: Interval,
+    finished: bool,
+}
+
+impl EventStream {
+    fn into_stream(self) -> impl Stream<Item = Result<SseEvent, Infallible>> {
+        stream::unfold(self, |mut events| async move {
+            let event = events.next().await?;
+            Some((Ok(event), events))
+        })
+    }
+
+    async fn next(&mut self) -> Option<SseEvent> {
+        loop {
+            if self.finished {
+                // Everything the test published is in the log by now
+                let event = self.cursor.next_queued()?;
+                if self.subscription.matches(&event.message) {
+                    return Some(sse_event(Some(event.seq), &event));
+                }
+                continue;
+            }
+
+            tokio::select! {
+                delivery = self.cursor.next() => match delivery? {
+                    Delivery::Event(event) if self.subscription.matches(&event.message) => {
+                        return Some(sse_event(Some(event.seq), &event));
+                    }
+                    Delivery::Event(_) => {}
+                    Delivery::Lagged(notice) => {
+                        warn!("Event stream client lagged: {:?}", notice);
+                        return Some(sse_event(None, &notice));
+                    }
+                },
+                _ = self.finish_check.tick() => {
+                    let tests = self.state.active_tests.lock().unwrap();
+                    if tests.get(&self.test_id).is_none_or(TestRun::is_finished) {
+                        self.finished = true;
+                        self.cursor.catch_up();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An SSE event carrying `value` as JSON; `seq` becomes its id so clients
+/// can resume with `Last-Event-ID`.
+fn sse_event<T: Serialize>(seq: Option<u64>, value: &T) -> SseEvent {
+    let event = SseEvent::default().json_data(value).unwrap_or_else(|e| {
+        error!("Failed to serialize message: {}", e);
+        SseEvent::default().data(r#"{"type":"error","message":"Serialization error"}"#)
+    });
+    match seq {
+        Some(seq) => event.id(seq.to_string()),
+        None => event,
     }
 }
 
+/// Streams the messages matching the subscription in the query string
+/// (`?test_id=`, `?target=`, `?types=`; every message when omitted) and
+/// accepts commands to start, stop and list tests and to change the
+/// subscriptions, answering each with an `Ack` or `Error` reply. With
+/// `?since=<seq>` the logged messages after that sequence number are
+/// replayed first.
 async fn websocket_handler(
-    ws: WebSocketUpgrade,
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+    query: Result<Query<SubscriptionQuery>, QueryRejection>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|socket| websocket_connection(socket, state))
+) -> Result<Response, ApiError> {
+    let Query(query) = query?;
+    let since = query.since;
+    let subscription = query.into_subscription();
+    subscription
+        .validate()
+        .map_err(|e| ApiError::invalid_query(e).with_field("types"))?;
+
+    Ok(ws?.on_upgrade(move |socket| websocket_connection(socket, state, subscription, since)))
 }
 
 async fn websocket_connection(
-    socket: axum::extract::ws::WebSocket,
+    socket: WebSocket,
     state: AppState,
+    subscription: Subscription,
+    since: Option<u64>,
 ) {
-    use axum::extract::ws::Message;
-    use futures_util::{sink::SinkExt, stream::StreamExt};
-    
+    use futures_util::stream::StreamExt;
+
     let (mut sender, mut receiver) = socket.split();
-    let mut rx = state.broadcast_tx.subscribe();
+    let mut cursor = EventCursor::new(&state.events, since);
+    let subscriptions = Arc::new(Mutex::new(Subscriptions::new(subscription)));
+    // Command replies go out through the send task
+    let (reply_tx, mut reply_rx) = mpsc::unbounded_channel::<CommandReply>();
 
     info!("New WebSocket connection established");
 
-    // Spawn task to send messages to client
+    // Spawn task to send matching messages and replies to client
+    let send_subscriptions = subscriptions.clone();
     let send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            let json_msg = serde_json::to_string(&msg).unwrap_or_else(|e| {
-                error!("Failed to serialize message: {}", e);
-                r#"{"type":"error","message":"Serialization error"}"#.to_string()
-            });
-            
-            if sender
-                .send(Message::Text(json_msg))
-                .await
-                .is_err()
-            {
+        loop {
+            let sent = tokio::select! {
+                delivery = cursor.next() => match delivery {
+                    Some(Delivery::Event(event)) => {
+                        let matches = send_subscriptions.lock().unwrap().matches(&event.message);
+                        !matches || send_frame(&mut sender, &event).await
+                    }
+                    Some(Delivery::Lagged(notice)) => {
+                        warn!("WebSocket client lagged: {:?}", notice);
+                        send_frame(&mut sender, &notice).await
+                    }
+                    None => false,
+                },
+                Some(reply) = reply_rx.recv() => send_frame(&mut sender, &reply).await,
+            };
+            if !sent {
                 break;
             }
         }
     });
 
-    // Spawn task to handle incoming messages (if needed)
+    // Spawn task to run the client's commands
     let recv_task = tokio::spawn(async move {
         while let Some(msg) = receiver.next().await {
             match msg {
+                Ok(Message::Text(text)) => {
+                    let reply = match CommandRequest::parse(&text) {
+                        Ok(request) => run_command(&state, &subscriptions, request),
+                        Err(reply) => reply,
+                    };
+                    if reply_tx.send(reply).is_err() {
+                        break;
+                    }
+                }
                 Ok(Message::Close(_)) => break,
                 Ok(Message::Pong(_)) => {
                     // Handle pong if needed
@@ -345,7 +655,62 @@ async fn websocket_connection(
     info!("WebSocket connection closed");
 }
 
+/// Serializes and sends one frame; false once the client has gone.
+async fn send_frame<T: Serialize>(sender: &mut SplitSink<WebSocket, Message>, value: &T) -> bool {
+    use futures_util::sink::SinkExt;
+
+    let json_msg = serde_json::to_string(value).unwrap_or_else(|e| {
+        error!("Failed to serialize message: {}", e);
+        r#"{"type":"error","message":"Serialization error"}"#.to_string()
+    });
+    sender.send(Message::Text(json_msg)).await.is_ok()
+}
+
+fn run_command(state: &AppState, subscriptions: &Mutex<Subscriptions>, request: CommandRequest) -> CommandReply {
+    let CommandRequest { id, command } = request;
+
+    let result = match &command {
+        Command::Start { plan } => TestPlan::from_value(plan.clone())
+            .map_err(reject_plan)
+            .and_then(|plan| launch_test(state, plan))
+            .and_then(|response| to_json(&response)),
+        Command::Stop { test_id, target } => stop_run(state, test_id, target.as_deref()),
+        Command::Pause { test_id } => pause_run(state, test_id, true),
+        Command::Resume { test_id } => pause_run(state, test_id, false),
+        Command::List => to_json(&active_statuses(state)),
+        Command::Subscribe { subscription } => subscription
+            .validate()
+            .map_err(|e| ApiError::invalid_query(e).with_field("types"))
+            .map(|()| {
+                let mut subscriptions = subscriptions.lock().unwrap();
+                subscriptions.subscribe(subscription.clone());
+                serde_json::json!({ "subscriptions": subscriptions.list() })
+            }),
+        Command::Unsubscribe { test_id } => {
+            let mut subscriptions = subscriptions.lock().unwrap();
+            subscriptions.unsubscribe(test_id.as_deref());
+            Ok(serde_json::json!({ "subscriptions": subscriptions.list() }))
+        }
+    };
+
+    CommandReply::new(id, &command, result)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<serde_json::Value, ApiError> {
+    serde_json::to_value(value).map_err(|e| ApiError::internal(e.to_string()))
+}
+
+/// Status of every test still held in memory, oldest first.
+fn active_statuses(state: &AppState) -> Vec<TestStatusResponse> {
+    let tests = state.active_tests.lock().unwrap();
+    let mut statuses: Vec<TestStatusResponse> = tests.values().map(status_response).collect();
+    statuses.sort_by_key(|status| status.started_at);
+    statuses
+}
+
 async fn cleanup_completed_tests(state: &AppState) {
     let mut tests = state.active_tests.lock().unwrap();
-    tests.retain(|_, test| !matches!(test.status(), LoadTestStatus::Completed | LoadTestStatus::Failed));
+    tests.retain(|_, run| !run.is_finished());
+    // Replay is only offered while a test is still held in memory
+    state.events.retain_tests(|test_id| tests.contains_key(test_id));
 }
\ No newline at end of file
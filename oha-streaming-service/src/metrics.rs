@@ -0,0 +1,338 @@
+use crate::timed_client::PhaseTimings;
+use hdrhistogram::Histogram;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Bounds and precision of every latency histogram in a load test. All
+/// histograms record microseconds; values above `highest_trackable_us` are
+/// clamped to it rather than dropped.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistogramConfig {
+    #[serde(default = "default_lowest_discernible_us")]
+    pub lowest_discernible_us: u64,
+    #[serde(default = "default_highest_trackable_us")]
+    pub highest_trackable_us: u64,
+    #[serde(default = "default_significant_figures")]
+    pub significant_figures: u8,
+}
+
+fn default_lowest_discernible_us() -> u64 {
+    1
+}
+
+fn default_highest_trackable_us() -> u64 {
+    60_000_000
+}
+
+fn default_significant_figures() -> u8 {
+    3
+}
+
+impl Default for HistogramConfig {
+    fn default() -> Self {
+        Self {
+            lowest_discernible_us: default_lowest_discernible_us(),
+            highest_trackable_us: default_highest_trackable_us(),
+            significant_figures: default_significant_figures(),
+        }
+    }
+}
+
+impl HistogramConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        Histogram::<u64>::new_with_bounds(
+            self.lowest_discernible_us,
+            self.highest_trackable_us,
+            self.significant_figures,
+        )
+        .map(|_| ())
+        .map_err(|e| format!("Invalid histogram config: {:?}", e))
+    }
+
+    /// Builds an empty histogram, falling back to the defaults if this config
+    /// was never validated and turns out to be invalid.
+    pub fn new_histogram(&self) -> Histogram<u64> {
+        Histogram::new_with_bounds(
+            self.lowest_discernible_us,
+            self.highest_trackable_us,
+            self.significant_figures,
+        )
+        .unwrap_or_else(|_| HistogramConfig::default().new_histogram())
+    }
+}
+
+pub fn us_to_ms(us: f64) -> f64 {
+    us / 1000.0
+}
+
+/// Outcome bucket a request's latency is recorded under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StatusClass {
+    #[serde(rename = "1xx")]
+    Informational,
+    #[serde(rename = "2xx")]
+    Success,
+    #[serde(rename = "3xx")]
+    Redirection,
+    #[serde(rename = "4xx")]
+    ClientError,
+    #[serde(rename = "5xx")]
+    ServerError,
+    /// No HTTP response at all: timeouts, refused connections, resets.
+    #[serde(rename = "transport_error")]
+    TransportError,
+}
+
+impl StatusClass {
+    pub fn from_status(status: StatusCode) -> Self {
+        match status.as_u16() {
+            100..=199 => StatusClass::Informational,
+            200..=299 => StatusClass::Success,
+            300..=399 => StatusClass::Redirection,
+            400..=499 => StatusClass::ClientError,
+            _ => StatusClass::ServerError,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub avg_latency_ms: f64,
+    pub min_latency_ms: f64,
+    pub max_latency_ms: f64,
+    pub p50_latency_ms: f64,
+    pub p90_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub p99_latency_ms: f64,
+    pub avg_latency_us: f64,
+    pub min_latency_us: u64,
+    pub max_latency_us: u64,
+    pub p50_latency_us: u64,
+    pub p90_latency_us: u64,
+    pub p95_latency_us: u64,
+    pub p99_latency_us: u64,
+}
+
+impl LatencySummary {
+    pub fn from_histogram(histogram: &Histogram<u64>) -> Self {
+        let min = histogram.min();
+        let max = histogram.max();
+        let p50 = histogram.value_at_quantile(0.5);
+        let p90 = histogram.value_at_quantile(0.9);
+        let p95 = histogram.value_at_quantile(0.95);
+        let p99 = histogram.value_at_quantile(0.99);
+
+        Self {
+            count: histogram.len(),
+            avg_latency_ms: us_to_ms(histogram.mean()),
+            min_latency_ms: us_to_ms(min as f64),
+            max_latency_ms: us_to_ms(max as f64),
+            p50_latency_ms: us_to_ms(p50 as f64),
+            p90_latency_ms: us_to_ms(p90 as f64),
+            p95_latency_ms: us_to_ms(p95 as f64),
+            p99_latency_ms: us_to_ms(p99 as f64),
+            avg_latency_us: histogram.mean(),
+            min_latency_us: min,
+            max_latency_us: max,
+            p50_latency_us: p50,
+            p90_latency_us: p90,
+            p95_latency_us: p95,
+            p99_latency_us: p99,
+        }
+    }
+}
+
+/// Traffic and latency over one progress tick only, as opposed to the
+/// cumulative figures since the start of the test.
+#[derive(Debug, Clone, Serialize)]
+pub struct IntervalMetrics {
+    pub duration_seconds: f64,
+    pub requests: u64,
+    pub errors: u64,
+    pub rps: f64,
+    pub p50_latency_ms: f64,
+    pub p90_latency_ms: f64,
+    pub p99_latency_ms: f64,
+    pub max_latency_ms: f64,
+    pub p50_latency_us: u64,
+    pub p90_latency_us: u64,
+    pub p99_latency_us: u64,
+    pub max_latency_us: u64,
+}
+
+impl IntervalMetrics {
+    pub fn new(duration_seconds: f64, requests: u64, errors: u64, histogram: &Histogram<u64>) -> Self {
+        let p50 = histogram.value_at_quantile(0.5);
+        let p90 = histogram.value_at_quantile(0.9);
+        let p99 = histogram.value_at_quantile(0.99);
+        let max = histogram.max();
+
+        Self {
+            duration_seconds,
+            requests,
+            errors,
+            rps: requests as f64 / duration_seconds.max(f64::EPSILON),
+            p50_latency_ms: us_to_ms(p50 as f64),
+            p90_latency_ms: us_to_ms(p90 as f64),
+            p99_latency_ms: us_to_ms(p99 as f64),
+            max_latency_ms: us_to_ms(max as f64),
+            p50_latency_us: p50,
+            p90_latency_us: p90,
+            p99_latency_us: p99,
+            max_latency_us: max,
+        }
+    }
+}
+
+/// One latency histogram per status class, covering failed requests as well
+/// as successful ones.
+#[derive(Debug, Clone)]
+pub struct StatusClassHistograms {
+    config: HistogramConfig,
+    histograms: HashMap<StatusClass, Histogram<u64>>,
+}
+
+impl StatusClassHistograms {
+    pub fn new(config: HistogramConfig) -> Self {
+        Self {
+            config,
+            histograms: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, class: StatusClass, latency_us: u64) {
+        let config = self.config;
+        self.histograms
+            .entry(class)
+            .or_insert_with(|| config.new_histogram())
+            .saturating_record(latency_us);
+    }
+
+    pub fn summaries(&self) -> HashMap<StatusClass, LatencySummary> {
+        self.histograms
+            .iter()
+            .map(|(class, histogram)| (*class, LatencySummary::from_histogram(histogram)))
+            .collect()
+    }
+}
+
+/// One histogram per request phase, fed by the timed client.
+#[derive(Debug, Clone)]
+pub struct PhaseHistograms {
+    dns: Histogram<u64>,
+    connect: Histogram<u64>,
+    tls: Histogram<u64>,
+    ttfb: Histogram<u64>,
+    body: Histogram<u64>,
+}
+
+impl PhaseHistograms {
+    pub fn new(config: HistogramConfig) -> Self {
+        Self {
+            dns: config.new_histogram(),
+            connect: config.new_histogram(),
+            tls: config.new_histogram(),
+            ttfb: config.new_histogram(),
+            body: config.new_histogram(),
+        }
+    }
+
+    /// Records the phases a request went through; connection phases skipped
+    /// on a reused connection are left out rather than counted as zero.
+    pub fn record(&mut self, phases: &PhaseTimings) {
+        let phases_us = [
+            (&mut self.dns, phases.dns),
+            (&mut self.connect, phases.connect),
+            (&mut self.tls, phases.tls),
+            (&mut self.ttfb, Some(phases.ttfb)),
+            (&mut self.body, Some(phases.body)),
+        ];
+
+        for (histogram, duration) in phases_us {
+            if let Some(duration) = duration {
+                histogram.saturating_record(duration.as_micros() as u64);
+            }
+        }
+    }
+
+    pub fn summary(&self) -> PhaseSummary {
+        PhaseSummary {
+            dns: LatencySummary::from_histogram(&self.dns),
+            connect: LatencySummary::from_histogram(&self.connect),
+            tls: LatencySummary::from_histogram(&self.tls),
+            ttfb: LatencySummary::from_histogram(&self.ttfb),
+            body: LatencySummary::from_histogram(&self.body),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseSummary {
+    pub dns: LatencySummary,
+    pub connect: LatencySummary,
+    pub tls: LatencySummary,
+    pub ttfb: LatencySummary,
+    pub body: LatencySummary,
+}
+
+/// Counters and latency histograms for one scenario endpoint.
+#[derive(Debug, Clone)]
+pub struct EndpointStats {
+    pub requests: u64,
+    pub successful: u64,
+    pub failed: u64,
+    /// Successful responses only, matching the aggregate histogram.
+    pub latency_histogram: Histogram<u64>,
+    pub status_class_histograms: StatusClassHistograms,
+}
+
+impl EndpointStats {
+    pub fn new(config: HistogramConfig) -> Self {
+        Self {
+            requests: 0,
+            successful: 0,
+            failed: 0,
+            latency_histogram: config.new_histogram(),
+            status_class_histograms: StatusClassHistograms::new(config),
+        }
+    }
+
+    pub fn record(&mut self, class: StatusClass, latency_us: u64) {
+        self.requests += 1;
+
+        if class == StatusClass::Success {
+            self.successful += 1;
+            self.latency_histogram.saturating_record(latency_us);
+        } else {
+            self.failed += 1;
+        }
+
+        self.status_class_histograms.record(class, latency_us);
+    }
+
+    pub fn result(&self, elapsed_seconds: f64) -> EndpointResult {
+        EndpointResult {
+            total_requests: self.requests,
+            successful_requests: self.successful,
+            failed_requests: self.failed,
+            requests_per_second: self.requests as f64 / elapsed_seconds.max(f64::EPSILON),
+            latency: LatencySummary::from_histogram(&self.latency_histogram),
+            latency_by_status_class: self.status_class_histograms.summaries(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointResult {
+    pub total_requests: u64,
+    pub successful_requests: u64,
+    pub failed_requests: u64,
+    pub requests_per_second: f64,
+    /// Successful responses only.
+    #[serde(flatten)]
+    pub latency: LatencySummary,
+    pub latency_by_status_class: HashMap<StatusClass, LatencySummary>,
+}
@@ -0,0 +1,201 @@
+use crate::{load_tester::LoadMode, pause::PauseSwitch, profile::LoadProfile};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+/// Shared send schedule for every worker of a load test.
+///
+/// Slot `n` is due at the point where the load profile has scheduled `n`
+/// requests, computed in floating-point seconds so rates above 1000 RPS do not
+/// collapse to a zero interval. Workers claim slots with a single atomic
+/// increment, which keeps the combined rate across all connections equal to
+/// the configured rate.
+///
+/// In `OpenLoop` mode every slot is owed: a send that falls behind claims
+/// slots that are already due and sends immediately until it catches up, so
+/// latency can be measured from the intended send time. In `ClosedLoop` mode
+/// slots nobody claimed in time are skipped, so workers that were busy, or a
+/// stage with no connections, do not turn into a burst afterwards.
+///
+/// Time spent paused does not count: elapsed time stops while paused and
+/// every slot not yet sent is pushed back by the length of the pause.
+///
+/// `retune` changes the profile from the current point on; slots already
+/// waiting are rescheduled to match. `stop` ends every wait at once.
+#[derive(Debug)]
+pub struct Pacer {
+    start: Instant,
+    profile: watch::Sender<LoadProfile>,
+    next_slot: AtomicU64,
+    mode: LoadMode,
+    stopped: watch::Sender<bool>,
+    pause: PauseSwitch,
+    /// Paused time accumulated before `start`, which is not ours to skip.
+    paused_before: Duration,
+}
+
+impl Pacer {
+    pub fn new(start: Instant, profile: LoadProfile, mode: LoadMode, pause: PauseSwitch) -> Self {
+        Self {
+            start,
+            profile: watch::Sender::new(profile),
+            next_slot: AtomicU64::new(0),
+            mode,
+            stopped: watch::Sender::new(false),
+            paused_before: pause.paused_time(),
+            pause,
+        }
+    }
+
+    pub fn profile(&self) -> watch::Ref<'_, LoadProfile> {
+        self.profile.borrow()
+    }
+
+    /// Notified on every `retune`.
+    pub fn subscribe(&self) -> watch::Receiver<LoadProfile> {
+        self.profile.subscribe()
+    }
+
+    /// Holds the rate and/or connection count at the given values from now
+    /// to the end of the profile.
+    pub fn retune(&self, rate: Option<u64>, connections: Option<u64>) {
+        let elapsed = self.elapsed_seconds();
+        self.profile
+            .send_modify(|profile| profile.override_from(elapsed, rate, connections));
+    }
+
+    /// Makes every pending and future `wait` return `None`, so workers
+    /// sleeping until a distant slot see the stop straight away.
+    pub fn stop(&self) {
+        self.stopped.send_replace(true);
+    }
+
+    /// Time since `start`, not counting time spent paused.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed().saturating_sub(self.paused())
+    }
+
+    pub fn elapsed_seconds(&self) -> f64 {
+        self.elapsed().as_secs_f64()
+    }
+
+    fn paused(&self) -> Duration {
+        self.pause.paused_time().saturating_sub(self.paused_before)
+    }
+
+    /// Claims the next send slot and waits until it is due, parking for as
+    /// long as the test is paused. `None` once the schedule has run past the
+    /// end of the profile or the pacer has been stopped.
+    pub async fn wait(&self) -> Option<Instant> {
+        let slot = match self.mode {
+            LoadMode::OpenLoop => self.next_slot.fetch_add(1, Ordering::Relaxed),
+            LoadMode::ClosedLoop => {
+                // Skip ahead to the slot due now rather than owe the missed ones
+                let current = self.profile.borrow().requests_at(self.elapsed_seconds()).floor() as u64;
+                let claimed = self
+                    .next_slot
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| Some(next.max(current) + 1))
+                    .unwrap_or_else(|next| next);
+                claimed.max(current)
+            }
+        };
+
+        let mut stopped = self.stopped.subscribe();
+        loop {
+            tokio::select! {
+                _ = self.pause.resumed() => {}
+                _ = stopped.wait_for(|stopped| *stopped) => return None,
+            }
+            let mut retuned = self.profile.subscribe();
+            let offset = Duration::from_secs_f64(retuned.borrow_and_update().offset_of_request(slot)?);
+            let due = self.start + self.paused() + offset;
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(due.into()) => return Some(due),
+                // A pause moves the slot back; wait for the resume
+                _ = self.pause.paused() => {}
+                // A new rate moves the slot
+                _ = retuned.changed() => {}
+                _ = stopped.wait_for(|stopped| *stopped) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// A pacer over `rate` RPS for `duration` seconds that started `ago`.
+    fn pacer(mode: LoadMode, rate: u64, duration: u64, ago: Duration) -> Pacer {
+        let config = serde_json::from_value(json!({
+            "duration_seconds": duration,
+            "connections": 1,
+            "rate_per_second": rate,
+        }))
+        .unwrap();
+        Pacer::new(Instant::now() - ago, LoadProfile::from_config(&config), mode, PauseSwitch::new())
+    }
+
+    #[tokio::test]
+    async fn open_loop_owes_every_slot() {
+        let pacer = pacer(LoadMode::OpenLoop, 100, 10, Duration::from_millis(500));
+
+        // Slots 0, 1 and 2 are long past due and come back at once
+        for slot in 0..3 {
+            let due = pacer.wait().await.unwrap();
+            assert_eq!(due - pacer.start, Duration::from_millis(10 * slot));
+        }
+    }
+
+    #[tokio::test]
+    async fn closed_loop_skips_missed_slots() {
+        let pacer = pacer(LoadMode::ClosedLoop, 100, 10, Duration::from_millis(500));
+
+        // The 50 slots before now are dropped rather than sent in a burst
+        let first = pacer.wait().await.unwrap() - pacer.start;
+        assert!(first >= Duration::from_millis(500) && first < Duration::from_millis(600), "{:?}", first);
+
+        let second = pacer.wait().await.unwrap() - pacer.start;
+        assert_eq!(second - first, Duration::from_millis(10));
+    }
+
+    #[tokio::test]
+    async fn ends_with_the_profile() {
+        let pacer = pacer(LoadMode::ClosedLoop, 100, 1, Duration::from_secs(2));
+        assert_eq!(pacer.wait().await, None);
+    }
+
+    #[tokio::test]
+    async fn stop_ends_a_pending_wait() {
+        let pacer = Arc::new(pacer(LoadMode::ClosedLoop, 1, 60, Duration::ZERO));
+        pacer.wait().await.unwrap();
+
+        // The next slot is a second away
+        let waiting = tokio::spawn({
+            let pacer = pacer.clone();
+            async move { pacer.wait().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        pacer.stop();
+
+        let result = tokio::time::timeout(Duration::from_millis(200), waiting).await;
+        assert_eq!(result.unwrap().unwrap(), None);
+        assert_eq!(pacer.wait().await, None);
+    }
+
+    #[tokio::test]
+    async fn paused_time_is_left_out() {
+        let pacer = pacer(LoadMode::ClosedLoop, 100, 10, Duration::ZERO);
+        pacer.pause.pause();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(pacer.elapsed() < Duration::from_millis(20), "{:?}", pacer.elapsed());
+        pacer.pause.resume();
+        assert_eq!(pacer.wait().await.unwrap() - pacer.start, pacer.paused());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+/// Pause control of one load test, shared by its workers, pacer and progress
+/// reporter. Keeps a running total of the time spent paused so elapsed time,
+/// rates and the send schedule can leave it out.
+#[derive(Debug, Clone)]
+pub struct PauseSwitch {
+    state: Arc<watch::Sender<PauseState>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PauseState {
+    paused_since: Option<Instant>,
+    /// Paused time of the pauses that have ended.
+    paused_total: Duration,
+}
+
+impl Default for PauseSwitch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PauseSwitch {
+    pub fn new() -> Self {
+        let (state, _) = watch::channel(PauseState::default());
+        Self {
+            state: Arc::new(state),
+        }
+    }
+
+    /// `false` when already paused.
+    pub fn pause(&self) -> bool {
+        self.state.send_if_modified(|state| {
+            if state.paused_since.is_some() {
+                return false;
+            }
+            state.paused_since = Some(Instant::now());
+            true
+        })
+    }
+
+    /// How long the pause that just ended lasted; `None` when not paused.
+    pub fn resume(&self) -> Option<Duration> {
+        let mut paused_for = None;
+        self.state.send_if_modified(|state| match state.paused_since.take() {
+            Some(since) => {
+                let elapsed = since.elapsed();
+                state.paused_total += elapsed;
+                paused_for = Some(elapsed);
+                true
+            }
+            None => false,
+        });
+        paused_for
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.borrow().paused_since.is_some()
+    }
+
+    /// Total time spent paused so far, including a pause still in progress.
+    pub fn paused_time(&self) -> Duration {
+        let state = *self.state.borrow();
+        state.paused_total + state.paused_since.map_or(Duration::ZERO, |since| since.elapsed())
+    }
+
+    /// Returns at once when running, otherwise once resumed.
+    pub async fn resumed(&self) {
+        let mut rx = self.state.subscribe();
+        // The sender lives as long as `self`, so this cannot fail
+        let _ = rx.wait_for(|state| state.paused_since.is_none()).await;
+    }
+
+    /// Returns at once when paused, otherwise once paused.
+    pub async fn paused(&self) {
+        let mut rx = self.state.subscribe();
+        let _ = rx.wait_for(|state| state.paused_since.is_some()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_and_resume_only_once() {
+        let switch = PauseSwitch::new();
+        assert_eq!(switch.resume(), None);
+        assert!(switch.pause());
+        assert!(!switch.pause());
+        assert!(switch.is_paused());
+
+        assert!(switch.resume().is_some());
+        assert_eq!(switch.resume(), None);
+        assert!(!switch.is_paused());
+    }
+
+    #[test]
+    fn totals_every_pause() {
+        let switch = PauseSwitch::new();
+        assert_eq!(switch.paused_time(), Duration::ZERO);
+
+        let mut total = Duration::ZERO;
+        for _ in 0..2 {
+            switch.pause();
+            std::thread::sleep(Duration::from_millis(20));
+            // A pause in progress counts too
+            assert!(switch.paused_time() >= total + Duration::from_millis(20));
+            total += switch.resume().unwrap();
+        }
+        assert_eq!(switch.paused_time(), total);
+
+        // Running time is not counted
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(switch.paused_time(), total);
+    }
+
+    #[tokio::test]
+    async fn resumed_waits_for_resume() {
+        let switch = PauseSwitch::new();
+        switch.resumed().await;
+
+        switch.pause();
+        let waiting = tokio::spawn({
+            let switch = switch.clone();
+            async move { switch.resumed().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        switch.resume();
+        tokio::time::timeout(Duration::from_millis(200), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,581 @@
+use crate::{
+    load_tester::{LoadMode, LoadTestConfig},
+    metrics::HistogramConfig,
+    profile::{LoadProfile, Stage},
+    request::RequestSpec,
+    scenario::{RequestTemplate, Scenario, REQUEST_TEMPLATE_FIELDS},
+    test_run::TargetSpec,
+    threshold::{CompiledThreshold, Threshold},
+    trial::{TrialConfig, TrialExecution, TrialOrder},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashSet, fmt, path::Path};
+use url::Url;
+
+// Upper bounds on what a single plan may ask for
+pub const MAX_TARGETS: usize = 32;
+pub const MAX_TARGET_NAME_LENGTH: usize = 64;
+pub const MAX_CONNECTIONS: u64 = 10_000;
+pub const MAX_RATE_PER_SECOND: u64 = 1_000_000;
+/// Longest test, warm-up, cooldown or threshold grace period: 24 hours.
+pub const MAX_DURATION_SECONDS: u64 = 86_400;
+pub const MAX_TRIALS: u64 = 100;
+pub const MAX_NOTE_LENGTH: usize = 256;
+/// Every target and trial allocates several histograms up front, so their
+/// size is capped: one hour at 3 significant figures is about 190 KB each.
+pub const MAX_HIGHEST_TRACKABLE_US: u64 = 3_600_000_000;
+pub const MAX_SIGNIFICANT_FIGURES: u64 = 3;
+
+/// Everything needed to run a test: the body of `POST /api/test/start` and
+/// the document the CLI runs. Omitted settings take their defaults; unknown
+/// settings are rejected so typos in plan files do not go unnoticed.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestPlan {
+    #[serde(default)]
+    pub targets: Vec<TargetSpec>,
+    // Legacy node/bun pair, appended to `targets` as "node" and "bun"
+    pub node_url: Option<String>,
+    pub bun_url: Option<String>,
+    pub duration_seconds: Option<u64>,
+    pub connections: Option<u64>,
+    pub rate_per_second: Option<u64>,
+    pub mode: Option<LoadMode>,
+    pub stages: Option<Vec<Stage>>,
+    pub request: Option<RequestSpec>,
+    pub scenario: Option<Vec<RequestTemplate>>,
+    pub histogram: Option<HistogramConfig>,
+    pub phase_timing: Option<bool>,
+    pub trials: Option<TrialConfig>,
+    pub warmup_seconds: Option<u64>,
+    pub thresholds: Option<Vec<Threshold>>,
+}
+
+/// Serialization a plan document is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl PlanFormat {
+    /// The format a file extension implies; `None` for unknown extensions.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    /// The format a `Content-Type` header names, ignoring parameters such as
+    /// `charset`; `None` for other media types.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let essence = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "application/json" => Some(Self::Json),
+            "application/toml" | "application/x-toml" | "text/toml" | "text/x-toml" => Some(Self::Toml),
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Why a plan was rejected, with the path of the offending field (e.g.
+/// `targets[1].request.method`) when the problem can be pinned to one.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanError {
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl PlanError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: Some(field.into()),
+            message: message.into(),
+        }
+    }
+
+    fn from_path<E: fmt::Display>(error: serde_path_to_error::Error<E>) -> Self {
+        let field = error.path().to_string();
+        let message = error.inner().to_string();
+        // serde_yaml already prefixes its messages with the path
+        let message = message
+            .strip_prefix(&format!("{}: ", field))
+            .map(str::to_string)
+            .unwrap_or(message);
+
+        Self {
+            // The root path renders as "."
+            field: (field != ".").then_some(field),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}: {}", field, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+impl TestPlan {
+    /// Parses a plan document, reporting the path of the field that failed
+    /// to deserialize.
+    pub fn parse(source: &str, format: PlanFormat) -> Result<Self, PlanError> {
+        match format {
+            PlanFormat::Json => parse_json(source),
+            PlanFormat::Toml => deserialize(toml::Deserializer::new(source)),
+            PlanFormat::Yaml => deserialize(serde_yaml::Deserializer::from_str(source)),
+        }
+    }
+
+    /// Deserializes a plan embedded in another JSON document, reporting
+    /// field paths relative to the plan.
+    pub fn from_value(value: serde_json::Value) -> Result<Self, PlanError> {
+        deserialize(value)
+    }
+
+    pub fn resolved_targets(&self) -> Vec<TargetSpec> {
+        let legacy = [("node", &self.node_url), ("bun", &self.bun_url)]
+            .into_iter()
+            .filter_map(|(name, url)| {
+                url.as_ref().map(|url| TargetSpec {
+                    name: name.to_string(),
+                    url: url.clone(),
+                    request: None,
+                })
+            });
+
+        self.targets.iter().cloned().chain(legacy).collect()
+    }
+
+    /// The targets and config to run, after checking every setting against
+    /// its limits and that target names are unique and every threshold and
+    /// request is usable.
+    pub fn resolve(self) -> Result<(Vec<TargetSpec>, LoadTestConfig), PlanError> {
+        let targets = self.resolved_targets();
+        // Where each resolved target came from, for error paths
+        let target_field = |index: usize, field: &str| match index.checked_sub(self.targets.len()) {
+            None => format!("targets[{}].{}", index, field),
+            Some(0) if self.node_url.is_some() => "node_url".to_string(),
+            Some(_) => "bun_url".to_string(),
+        };
+
+        if targets.is_empty() {
+            return Err(PlanError::new("targets", "at least one target is required"));
+        }
+        if targets.len() > MAX_TARGETS {
+            return Err(PlanError::new(
+                "targets",
+                format!("at most {} targets are allowed", MAX_TARGETS),
+            ));
+        }
+        for (index, target) in targets.iter().enumerate() {
+            if target.name.is_empty() {
+                return Err(PlanError::new(target_field(index, "name"), "target name must not be empty"));
+            }
+            if target.name.chars().count() > MAX_TARGET_NAME_LENGTH {
+                return Err(PlanError::new(
+                    target_field(index, "name"),
+                    format!("target name must be at most {} characters", MAX_TARGET_NAME_LENGTH),
+                ));
+            }
+            check_url(&target.url).map_err(|e| PlanError::new(target_field(index, "url"), e))?;
+        }
+        let mut names = HashSet::new();
+        if let Some(index) = targets.iter().position(|t| !names.insert(t.name.as_str())) {
+            return Err(PlanError::new(
+                target_field(index, "name"),
+                format!("duplicate target name `{}`", targets[index].name),
+            ));
+        }
+
+        if let Some(duration) = self.duration_seconds {
+            check_range("duration_seconds", duration, 1, MAX_DURATION_SECONDS)?;
+        }
+        if let Some(connections) = self.connections {
+            check_range("connections", connections, 1, MAX_CONNECTIONS)?;
+        }
+        if let Some(rate) = self.rate_per_second {
+            check_range("rate_per_second", rate, 1, MAX_RATE_PER_SECOND)?;
+        }
+        if let Some(warmup) = self.warmup_seconds {
+            check_range("warmup_seconds", warmup, 0, MAX_DURATION_SECONDS)?;
+        }
+        // Stages may ramp down to zero, but not beyond the limits
+        for (index, stage) in self.stages.iter().flatten().enumerate() {
+            let field = |name: &str| format!("stages[{}].{}", index, name);
+            check_range(&field("duration_seconds"), stage.duration_seconds, 1, MAX_DURATION_SECONDS)?;
+            if let Some(rate) = stage.target_rate {
+                check_range(&field("target_rate"), rate, 0, MAX_RATE_PER_SECOND)?;
+            }
+            if let Some(connections) = stage.target_connections {
+                check_range(&field("target_connections"), connections, 0, MAX_CONNECTIONS)?;
+            }
+        }
+        let stages_total: u64 = self.stages.iter().flatten().map(|stage| stage.duration_seconds).sum();
+        if stages_total > MAX_DURATION_SECONDS {
+            return Err(PlanError::new(
+                "stages",
+                format!("stages must last at most {} seconds in total", MAX_DURATION_SECONDS),
+            ));
+        }
+        for (index, template) in self.scenario.iter().flatten().enumerate() {
+            if let Some(field) = template.unknown.keys().next() {
+                let expected: Vec<String> = REQUEST_TEMPLATE_FIELDS.iter().map(|f| format!("`{}`", f)).collect();
+                return Err(PlanError::new(
+                    format!("scenario[{}].{}", index, field),
+                    format!("unknown field `{}`, expected one of {}", field, expected.join(", ")),
+                ));
+            }
+        }
+        if let Some(histogram) = &self.histogram {
+            check_range(
+                "histogram.highest_trackable_us",
+                histogram.highest_trackable_us,
+                1,
+                MAX_HIGHEST_TRACKABLE_US,
+            )?;
+            check_range(
+                "histogram.significant_figures",
+                histogram.significant_figures as u64,
+                1,
+                MAX_SIGNIFICANT_FIGURES,
+            )?;
+        }
+        if let Some(trials) = &self.trials {
+            check_range("trials.count", trials.count as u64, 1, MAX_TRIALS)?;
+            check_range("trials.cooldown_seconds", trials.cooldown_seconds, 0, MAX_DURATION_SECONDS)?;
+            // Concurrent rounds start every target at once, so there is no order to shuffle
+            if trials.order == TrialOrder::Randomized && trials.execution == TrialExecution::Concurrent {
+                return Err(PlanError::new(
+                    "trials.order",
+                    "`Randomized` order needs `Sequential` execution; concurrent trials start together",
+                ));
+            }
+        }
+        for (index, threshold) in self.thresholds.iter().flatten().enumerate() {
+            check_range(
+                &format!("thresholds[{}].abort_grace_seconds", index),
+                threshold.abort_grace_seconds,
+                0,
+                MAX_DURATION_SECONDS,
+            )?;
+        }
+
+        let config = LoadTestConfig {
+            duration_seconds: self.duration_seconds.unwrap_or(60),
+            connections: self.connections.unwrap_or(10),
+            rate_per_second: self.rate_per_second,
+            mode: self.mode.unwrap_or_default(),
+            stages: self.stages.unwrap_or_default(),
+            request: self.request,
+            scenario: self.scenario.unwrap_or_default(),
+            histogram: self.histogram.unwrap_or_default(),
+            phase_timing: self.phase_timing.unwrap_or(false),
+            trials: self.trials.unwrap_or_default(),
+            warmup_seconds: self.warmup_seconds.unwrap_or(0),
+            thresholds: self.thresholds.unwrap_or_default(),
+        };
+
+        config
+            .histogram
+            .validate()
+            .map_err(|e| PlanError::new("histogram", e))?;
+
+        // Stages may idle for a while, but not for the whole test
+        if !config.stages.is_empty() {
+            let profile = LoadProfile::from_config(&config);
+            if profile.requests_at(profile.duration_seconds()) < 1.0 {
+                return Err(PlanError::new(
+                    "stages",
+                    "no stage schedules a request; give at least one a `target_rate` above 0",
+                ));
+            }
+            if config.mode == LoadMode::ClosedLoop && profile.max_connections() == 0 {
+                return Err(PlanError::new(
+                    "stages",
+                    "no stage has a connection to send from; give at least one a `target_connections` above 0",
+                ));
+            }
+        }
+
+        let names: Vec<&str> = targets.iter().map(|t| t.name.as_str()).collect();
+        for (index, threshold) in config.thresholds.iter().enumerate() {
+            CompiledThreshold::compile(threshold, &names)
+                .map_err(|e| PlanError::new(format!("thresholds[{}]", index), e))?;
+        }
+
+        // Reject requests that cannot be sent before anything starts
+        for (index, target) in targets.iter().enumerate() {
+            Scenario::prepare(&target.config(&config), &target.url).map_err(|e| {
+                let field = if !config.scenario.is_empty() {
+                    "scenario".to_string()
+                } else if target.request.is_some() {
+                    target_field(index, "request")
+                } else if config.request.is_some() {
+                    "request".to_string()
+                } else {
+                    target_field(index, "url")
+                };
+                PlanError::new(field, format!("target {}: {}", target.name, e))
+            })?;
+        }
+
+        Ok((targets, config))
+    }
+}
+
+/// Body of `PATCH /api/test/:test_id`: settings to change while a test
+/// runs. Omitted settings keep their current values.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanChange {
+    #[serde(default)]
+    pub rate_per_second: Option<u64>,
+    #[serde(default)]
+    pub connections: Option<u64>,
+    /// Free text shown with the change on the run's timeline.
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl PlanChange {
+    /// Parses a JSON change, checking it against the same limits as a plan.
+    pub fn parse(source: &str) -> Result<Self, PlanError> {
+        let change: Self = parse_json(source)?;
+
+        if change.rate_per_second.is_none() && change.connections.is_none() {
+            return Err(PlanError {
+                field: None,
+                message: "set at least one of `rate_per_second` and `connections`".to_string(),
+            });
+        }
+        if let Some(rate) = change.rate_per_second {
+            check_range("rate_per_second", rate, 1, MAX_RATE_PER_SECOND)?;
+        }
+        if let Some(connections) = change.connections {
+            check_range("connections", connections, 1, MAX_CONNECTIONS)?;
+        }
+        if change.note.as_ref().is_some_and(|note| note.chars().count() > MAX_NOTE_LENGTH) {
+            return Err(PlanError::new(
+                "note",
+                format!("must be at most {} characters", MAX_NOTE_LENGTH),
+            ));
+        }
+
+        Ok(change)
+    }
+}
+
+/// Targets must be absolute http(s) URLs with a host.
+fn check_url(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|e| format!("invalid URL `{}`: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("URL `{}` must use http or https", url));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(format!("URL `{}` has no host", url));
+    }
+    Ok(())
+}
+
+fn check_range(field: &str, value: u64, min: u64, max: u64) -> Result<(), PlanError> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(PlanError::new(
+            field,
+            format!("must be between {} and {}, got {}", min, max, value),
+        ))
+    }
+}
+
+/// Parses a whole JSON document, rejecting trailing characters.
+fn parse_json<T: DeserializeOwned>(source: &str) -> Result<T, PlanError> {
+    let mut deserializer = serde_json::Deserializer::from_str(source);
+    let value = deserialize(&mut deserializer)?;
+    deserializer.end().map_err(|e| PlanError {
+        field: None,
+        message: e.to_string(),
+    })?;
+    Ok(value)
+}
+
+fn deserialize<'de, D, T>(deserializer: D) -> Result<T, PlanError>
+where
+    D: serde::Deserializer<'de>,
+    D::Error: fmt::Display,
+    T: DeserializeOwned,
+{
+    serde_path_to_error::deserialize(deserializer).map_err(PlanError::from_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(source: &str, format: PlanFormat) -> Result<(Vec<TargetSpec>, LoadTestConfig), PlanError> {
+        TestPlan::parse(source, format)?.resolve()
+    }
+
+    fn error_field(source: &str) -> Option<String> {
+        resolve(source, PlanFormat::Json).unwrap_err().field
+    }
+
+    #[test]
+    fn resolves_defaults_and_legacy_targets() {
+        let (targets, config) = resolve(
+            r#"{"node_url": "http://localhost:3000", "bun_url": "http://localhost:3001"}"#,
+            PlanFormat::Json,
+        )
+        .unwrap();
+
+        let names: Vec<&str> = targets.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["node", "bun"]);
+        assert_eq!((config.duration_seconds, config.connections), (60, 10));
+        assert_eq!(config.mode, LoadMode::ClosedLoop);
+    }
+
+    #[test]
+    fn rejects_zero_connections() {
+        let error = resolve(
+            r#"{"targets": [{"name": "a", "url": "http://localhost:3000"}], "connections": 0}"#,
+            PlanFormat::Json,
+        )
+        .unwrap_err();
+
+        assert_eq!(error.field.as_deref(), Some("connections"));
+        assert_eq!(error.message, "must be between 1 and 10000, got 0");
+    }
+
+    #[test]
+    fn reports_the_path_of_invalid_fields() {
+        let target = r#""targets": [{"name": "a", "url": "http://localhost:3000"}]"#;
+
+        assert_eq!(
+            error_field(&format!(r#"{{{}, "stages": [{{"duration_seconds": 5, "target_rte": 5}}]}}"#, target)),
+            Some("stages[0].target_rte".to_string())
+        );
+        assert_eq!(
+            error_field(&format!(r#"{{{}, "trials": {{"count": 0}}}}"#, target)),
+            Some("trials.count".to_string())
+        );
+        assert_eq!(
+            error_field(r#"{"targets": [{"name": "a", "url": "ftp://localhost"}]}"#),
+            Some("targets[0].url".to_string())
+        );
+        assert_eq!(
+            error_field(&format!(r#"{{{}, "thresholds": ["b.p99 < 5ms"]}}"#, target)),
+            Some("thresholds[0]".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_stages_that_never_send() {
+        let plan = |stages: &str| {
+            format!(
+                r#"{{"targets": [{{"name": "a", "url": "http://localhost:3000"}}], "stages": {}}}"#,
+                stages
+            )
+        };
+
+        let error = resolve(&plan(r#"[{"duration_seconds": 5, "target_connections": 0}]"#), PlanFormat::Json)
+            .unwrap_err();
+        assert_eq!(error.field.as_deref(), Some("stages"));
+        assert!(error.message.contains("target_connections"));
+
+        let error = resolve(
+            &plan(r#"[{"duration_seconds": 5, "target_rate": 0}, {"duration_seconds": 5}]"#),
+            PlanFormat::Json,
+        )
+        .unwrap_err();
+        assert_eq!(error.field.as_deref(), Some("stages"));
+        assert!(error.message.contains("target_rate"));
+
+        // Idle stages are fine as long as another one sends
+        let idle_then_busy = r#"[
+            {"duration_seconds": 5, "target_connections": 0},
+            {"duration_seconds": 5, "target_connections": 2}
+        ]"#;
+        assert!(resolve(&plan(idle_then_busy), PlanFormat::Json).is_ok());
+    }
+
+    #[test]
+    fn limits_histogram_size() {
+        let plan = |histogram: &str| {
+            format!(
+                r#"{{"targets": [{{"name": "a", "url": "http://localhost:3000"}}], "histogram": {}}}"#,
+                histogram
+            )
+        };
+
+        assert_eq!(
+            error_field(&plan(r#"{"highest_trackable_us": 4611686018427387903, "significant_figures": 2}"#)),
+            Some("histogram.highest_trackable_us".to_string())
+        );
+        assert_eq!(
+            error_field(&plan(r#"{"significant_figures": 5}"#)),
+            Some("histogram.significant_figures".to_string())
+        );
+        assert_eq!(
+            error_field(&plan(r#"{"lowest_discernible_us": 1000, "highest_trackable_us": 1000}"#)),
+            Some("histogram".to_string())
+        );
+        assert!(resolve(&plan(r#"{"highest_trackable_us": 3600000000}"#), PlanFormat::Json).is_ok());
+    }
+
+    #[test]
+    fn randomized_order_needs_sequential_trials() {
+        let plan = |execution: &str| {
+            format!(
+                r#"{{"targets": [{{"name": "a", "url": "http://localhost:3000"}}],
+                    "trials": {{"count": 3, "order": "Randomized", "execution": "{}"}}}}"#,
+                execution
+            )
+        };
+
+        assert_eq!(error_field(&plan("Concurrent")), Some("trials.order".to_string()));
+        assert!(resolve(&plan("Sequential"), PlanFormat::Json).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_fields_in_scenario_entries() {
+        let source = r#"
+            [[targets]]
+            name = "a"
+            url = "http://localhost:3000"
+
+            [[scenario]]
+            name = "search"
+            path = "/search"
+            bogus = 1
+        "#;
+        let error = resolve(source, PlanFormat::Toml).unwrap_err();
+        assert_eq!(error.field.as_deref(), Some("scenario[0].bogus"));
+
+        let (_, config) = resolve(&source.replace("bogus = 1", "method = \"POST\""), PlanFormat::Toml).unwrap();
+        assert_eq!(config.scenario[0].request.method, "POST");
+    }
+
+    #[test]
+    fn plan_change_needs_a_setting() {
+        assert!(PlanChange::parse(r#"{"note": "only a note"}"#).is_err());
+        assert_eq!(
+            PlanChange::parse(r#"{"connections": 0}"#).unwrap_err().field.as_deref(),
+            Some("connections")
+        );
+
+        let change = PlanChange::parse(r#"{"rate_per_second": 200}"#).unwrap();
+        assert_eq!((change.rate_per_second, change.connections), (Some(200), None));
+    }
+}
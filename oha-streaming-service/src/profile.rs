@@ -0,0 +1,325 @@
+use crate::load_tester::LoadTestConfig;
+use serde::{Deserialize, Serialize};
+
+/// One step of a multi-stage load profile.
+///
+/// A stage without `target_rate` or `target_connections` holds the value
+/// reached by the previous stage. `Linear` stages ramp from the previous
+/// stage's target (or the flat config values for the first stage) to their
+/// own target over `duration_seconds`; `Step` stages switch immediately.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Stage {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub duration_seconds: u64,
+    #[serde(default)]
+    pub target_rate: Option<u64>,
+    #[serde(default)]
+    pub target_connections: Option<u64>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    #[default]
+    Step,
+    Linear,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    /// Index of the stage this segment belongs to; a stage split by
+    /// `override_from` spans two segments.
+    stage: usize,
+    start: f64,
+    duration: f64,
+    rate_from: f64,
+    rate_to: f64,
+    connections_from: f64,
+    connections_to: f64,
+}
+
+impl Segment {
+    fn fraction(&self, elapsed: f64) -> f64 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        ((elapsed - self.start) / self.duration).clamp(0.0, 1.0)
+    }
+
+    fn rate_at(&self, elapsed: f64) -> f64 {
+        self.rate_from + (self.rate_to - self.rate_from) * self.fraction(elapsed)
+    }
+
+    fn connections_at(&self, elapsed: f64) -> f64 {
+        self.connections_from + (self.connections_to - self.connections_from) * self.fraction(elapsed)
+    }
+
+    /// Number of requests scheduled over the whole segment.
+    fn request_count(&self) -> f64 {
+        (self.rate_from + self.rate_to) / 2.0 * self.duration
+    }
+
+    /// Number of requests scheduled from the segment start to `elapsed`.
+    fn count_at(&self, elapsed: f64) -> f64 {
+        let offset = (elapsed - self.start).clamp(0.0, self.duration);
+        (self.rate_from + self.rate_at(elapsed)) / 2.0 * offset
+    }
+
+    /// Offset in seconds from the segment start at which `count` requests
+    /// have been scheduled, inverting `count = r0 * t + a * t^2 / 2`.
+    fn offset_of(&self, count: f64) -> f64 {
+        let acceleration = (self.rate_to - self.rate_from) / self.duration;
+        if acceleration.abs() < f64::EPSILON {
+            return count / self.rate_from;
+        }
+
+        let discriminant = (self.rate_from * self.rate_from + 2.0 * acceleration * count).max(0.0);
+        (discriminant.sqrt() - self.rate_from) / acceleration
+    }
+}
+
+/// Target rate and concurrency over the lifetime of a load test, resolved
+/// from either `LoadTestConfig::stages` or the flat config fields.
+#[derive(Debug, Clone)]
+pub struct LoadProfile {
+    segments: Vec<Segment>,
+    stage_names: Vec<Option<String>>,
+}
+
+impl LoadProfile {
+    pub fn from_config(config: &LoadTestConfig) -> Self {
+        let base_rate = config.rate_per_second
+            .unwrap_or(config.connections * 10)
+            .max(1) as f64;
+        let base_connections = config.connections as f64;
+
+        if config.stages.is_empty() {
+            return Self {
+                segments: vec![Segment {
+                    stage: 0,
+                    start: 0.0,
+                    duration: config.duration_seconds as f64,
+                    rate_from: base_rate,
+                    rate_to: base_rate,
+                    connections_from: base_connections,
+                    connections_to: base_connections,
+                }],
+                stage_names: vec![None],
+            };
+        }
+
+        let mut segments = Vec::with_capacity(config.stages.len());
+        let mut start = 0.0;
+        let mut rate = base_rate;
+        let mut connections = base_connections;
+
+        for (index, stage) in config.stages.iter().enumerate() {
+            let rate_to = stage.target_rate.map_or(rate, |r| r as f64);
+            let connections_to = stage.target_connections.map_or(connections, |c| c as f64);
+            let (rate_from, connections_from) = match stage.interpolation {
+                Interpolation::Step => (rate_to, connections_to),
+                Interpolation::Linear => (rate, connections),
+            };
+
+            segments.push(Segment {
+                stage: index,
+                start,
+                duration: stage.duration_seconds as f64,
+                rate_from,
+                rate_to,
+                connections_from,
+                connections_to,
+            });
+
+            start += stage.duration_seconds as f64;
+            rate = rate_to;
+            connections = connections_to;
+        }
+
+        Self {
+            segments,
+            stage_names: config.stages.iter().map(|s| s.name.clone()).collect(),
+        }
+    }
+
+    pub fn duration_seconds(&self) -> f64 {
+        self.segments.last().map_or(0.0, |s| s.start + s.duration)
+    }
+
+    /// Index of the stage active at `elapsed` seconds; the last stage once the
+    /// profile has run out.
+    pub fn stage_at(&self, elapsed: f64) -> usize {
+        self.segment_at(elapsed).map_or(0, |s| s.stage)
+    }
+
+    fn segment_at(&self, elapsed: f64) -> Option<&Segment> {
+        self.segments
+            .iter()
+            .find(|s| elapsed < s.start + s.duration)
+            .or(self.segments.last())
+    }
+
+    pub fn stage_name(&self, index: usize) -> Option<String> {
+        self.stage_names.get(index).cloned().flatten()
+    }
+
+    pub fn rate_at(&self, elapsed: f64) -> f64 {
+        self.segment_at(elapsed).map_or(0.0, |s| s.rate_at(elapsed))
+    }
+
+    pub fn connections_at(&self, elapsed: f64) -> u64 {
+        self.segment_at(elapsed)
+            .map_or(0, |s| s.connections_at(elapsed).round() as u64)
+    }
+
+    /// Holds the rate and/or connection count at the given values from
+    /// `elapsed` seconds to the end, in place of whatever the stages had
+    /// planned. The schedule before `elapsed` is left as it was, so request
+    /// numbers already handed out keep their offsets.
+    pub fn override_from(&mut self, elapsed: f64, rate: Option<u64>, connections: Option<u64>) {
+        if rate.is_none() && connections.is_none() {
+            return;
+        }
+
+        // Split the segment in progress so only its remainder changes
+        if let Some(index) = self
+            .segments
+            .iter()
+            .position(|s| s.start < elapsed && elapsed < s.start + s.duration)
+        {
+            let segment = &mut self.segments[index];
+            let (rate_at, connections_at) = (segment.rate_at(elapsed), segment.connections_at(elapsed));
+            let remainder = Segment {
+                start: elapsed,
+                duration: segment.start + segment.duration - elapsed,
+                rate_from: rate_at,
+                connections_from: connections_at,
+                ..segment.clone()
+            };
+            segment.duration = elapsed - segment.start;
+            segment.rate_to = rate_at;
+            segment.connections_to = connections_at;
+            self.segments.insert(index + 1, remainder);
+        }
+
+        for segment in self.segments.iter_mut().filter(|s| s.start >= elapsed) {
+            if let Some(rate) = rate {
+                segment.rate_from = rate as f64;
+                segment.rate_to = rate as f64;
+            }
+            if let Some(connections) = connections {
+                segment.connections_from = connections as f64;
+                segment.connections_to = connections as f64;
+            }
+        }
+    }
+
+    /// Largest connection count any stage asks for, i.e. the worker pool size.
+    pub fn max_connections(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|s| s.connections_from.max(s.connections_to).round() as u64)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Offset in seconds at which request number `index` is due, or `None`
+    /// if the profile ends before it.
+    pub fn offset_of_request(&self, index: u64) -> Option<f64> {
+        let mut remaining = index as f64;
+
+        for segment in &self.segments {
+            let count = segment.request_count();
+            if remaining < count {
+                return Some(segment.start + segment.offset_of(remaining));
+            }
+            remaining -= count;
+        }
+
+        None
+    }
+
+    /// Number of requests scheduled before `elapsed` seconds, the inverse of
+    /// `offset_of_request`.
+    pub fn requests_at(&self, elapsed: f64) -> f64 {
+        self.segments.iter().map(|s| s.count_at(elapsed)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profile(config: serde_json::Value) -> LoadProfile {
+        LoadProfile::from_config(&serde_json::from_value(config).unwrap())
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn offsets_on_a_linear_ramp() {
+        // 10 -> 30 RPS over 10s: t^2 + 10t requests by t, 200 in total
+        let profile = profile(json!({
+            "duration_seconds": 60,
+            "connections": 4,
+            "rate_per_second": 10,
+            "stages": [{"duration_seconds": 10, "target_rate": 30, "interpolation": "Linear"}],
+        }));
+
+        assert_close(profile.offset_of_request(0).unwrap(), 0.0);
+        assert_close(profile.offset_of_request(11).unwrap(), 1.0);
+        assert_close(profile.offset_of_request(24).unwrap(), 2.0);
+        assert_close(profile.offset_of_request(199).unwrap(), 224f64.sqrt() - 5.0);
+        assert_eq!(profile.offset_of_request(200), None);
+
+        assert_close(profile.requests_at(2.0), 24.0);
+        assert_close(profile.requests_at(60.0), 200.0);
+        assert_close(profile.rate_at(5.0), 20.0);
+        assert_eq!(profile.duration_seconds(), 10.0);
+    }
+
+    #[test]
+    fn offsets_across_step_stages() {
+        let profile = profile(json!({
+            "duration_seconds": 60,
+            "connections": 2,
+            "rate_per_second": 50,
+            "stages": [
+                {"duration_seconds": 3, "target_connections": 0},
+                {"duration_seconds": 3, "target_rate": 100, "target_connections": 2},
+            ],
+        }));
+
+        // 150 requests in the first stage, then one every 10ms
+        assert_close(profile.offset_of_request(149).unwrap(), 2.98);
+        assert_close(profile.offset_of_request(150).unwrap(), 3.0);
+        assert_close(profile.offset_of_request(160).unwrap(), 3.1);
+        assert_eq!(profile.offset_of_request(450), None);
+        assert_eq!(profile.connections_at(1.0), 0);
+        assert_eq!(profile.max_connections(), 2);
+        assert_eq!(profile.stage_at(4.0), 1);
+    }
+
+    #[test]
+    fn override_keeps_the_schedule_before_it() {
+        let mut profile = profile(json!({
+            "duration_seconds": 10,
+            "connections": 2,
+            "rate_per_second": 10,
+        }));
+        profile.override_from(4.0, Some(20), None);
+
+        assert_close(profile.offset_of_request(39).unwrap(), 3.9);
+        assert_close(profile.offset_of_request(40).unwrap(), 4.0);
+        assert_close(profile.offset_of_request(60).unwrap(), 5.0);
+        assert_close(profile.requests_at(10.0), 160.0);
+        assert_eq!(profile.connections_at(8.0), 2);
+        assert_eq!(profile.stage_at(8.0), 0);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::{
+    comparison::ComparisonSummary,
+    load_tester::{LoadTestResult, LoadTestStatus},
+    store::StoredRun,
+    test_run::{Annotation, TestRun},
+    threshold::ThresholdReport,
+    trial::TrialAggregate,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Final results of a run with everything derived from them, as served by
+/// the results endpoint and written by the CLI.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestResultsResponse {
+    pub test_id: String,
+    pub status: LoadTestStatus,
+    pub started_at: DateTime<Utc>,
+    /// Only known for runs loaded from the result store.
+    pub completed_at: Option<DateTime<Utc>>,
+    pub results: Vec<LoadTestResult>,
+    pub comparison: Option<ComparisonSummary>,
+    /// Per-target spread across trials; empty without trials.
+    pub trials: Vec<TrialAggregate>,
+    /// Pass/fail outcome; `None` when the run had no thresholds.
+    pub thresholds: Option<ThresholdReport>,
+    /// Changes of rate or connections made while the run was going.
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+impl TestResultsResponse {
+    /// `None` until the run has finished and been analyzed.
+    pub fn from_run(run: &TestRun) -> Option<Self> {
+        let comparisons = run.comparisons()?;
+        let results = run.results();
+
+        Some(Self {
+            test_id: run.test_id.clone(),
+            status: run.status(),
+            started_at: run.started_at,
+            completed_at: None,
+            comparison: ComparisonSummary::from_results(&results, comparisons),
+            trials: TrialAggregate::from_results(&results),
+            thresholds: run.threshold_report.lock().unwrap().clone(),
+            annotations: run.annotations(),
+            results,
+        })
+    }
+
+    pub fn from_stored(stored: StoredRun) -> Self {
+        Self {
+            test_id: stored.test_id,
+            status: stored.status,
+            started_at: stored.started_at,
+            completed_at: Some(stored.completed_at),
+            comparison: ComparisonSummary::from_results(&stored.results, stored.comparisons),
+            trials: TrialAggregate::from_results(&stored.results),
+            thresholds: stored.thresholds,
+            annotations: stored.annotations,
+            results: stored.results,
+        }
+    }
+}
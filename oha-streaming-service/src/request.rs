@@ -0,0 +1,149 @@
+use bytes::Bytes;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Client, Method, Response, Url,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What to send on every request of a load test.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequestSpec {
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Extra query parameters appended to the target URL.
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Option<RequestBody>,
+    /// Overrides the content type implied by `body`.
+    #[serde(default)]
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RequestBody {
+    Raw(String),
+    Json(serde_json::Value),
+    Form(BTreeMap<String, String>),
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+impl Default for RequestSpec {
+    fn default() -> Self {
+        Self {
+            method: default_method(),
+            headers: BTreeMap::new(),
+            query: BTreeMap::new(),
+            body: None,
+            content_type: None,
+        }
+    }
+}
+
+impl RequestSpec {
+    /// Request used when none is configured. The system stress endpoints of
+    /// the bookstore servers only accept POSTs with a payload, so they keep
+    /// their historical defaults; everything else is a plain GET.
+    pub fn for_url(url: &str) -> Self {
+        let body = if url.contains("/api/system/stress-test") {
+            serde_json::json!({
+                "duration": 5000,
+                "intensity": 5,
+                "memoryIntensive": true
+            })
+        } else if url.contains("/api/system/heap-dump") {
+            serde_json::json!({})
+        } else if url.contains("/api/system/memory-stress") {
+            serde_json::json!({
+                "objectCount": 5000,
+                "objectSize": 500,
+                "duration": 10000
+            })
+        } else {
+            return Self::default();
+        };
+
+        Self {
+            method: "POST".to_string(),
+            body: Some(RequestBody::Json(body)),
+            ..Self::default()
+        }
+    }
+
+    /// Resolves the spec against `url` into a request that can be sent
+    /// repeatedly without re-encoding.
+    pub fn prepare(&self, url: &str) -> Result<PreparedRequest, String> {
+        let method = Method::from_bytes(self.method.to_uppercase().as_bytes())
+            .map_err(|_| format!("Invalid HTTP method: {}", self.method))?;
+
+        let mut url = Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+        if !self.query.is_empty() {
+            url.query_pairs_mut().extend_pairs(&self.query);
+        }
+
+        let (body, implied_content_type) = match &self.body {
+            None => (None, None),
+            Some(RequestBody::Raw(raw)) => (Some(Bytes::from(raw.clone())), None),
+            Some(RequestBody::Json(value)) => (
+                Some(Bytes::from(serde_json::to_vec(value).map_err(|e| e.to_string())?)),
+                Some("application/json"),
+            ),
+            Some(RequestBody::Form(fields)) => (
+                Some(Bytes::from(serde_urlencoded::to_string(fields).map_err(|e| e.to_string())?)),
+                Some("application/x-www-form-urlencoded"),
+            ),
+        };
+
+        let mut headers = HeaderMap::new();
+        if let Some(content_type) = self.content_type.as_deref().or(implied_content_type) {
+            let value = HeaderValue::from_str(content_type)
+                .map_err(|_| format!("Invalid content type: {}", content_type))?;
+            headers.insert(CONTENT_TYPE, value);
+        }
+
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("Invalid header name: {}", name))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| format!("Invalid value for header {}", name))?;
+            headers.insert(name, value);
+        }
+
+        Ok(PreparedRequest {
+            method,
+            url,
+            headers,
+            body,
+        })
+    }
+}
+
+/// A `RequestSpec` resolved against a target URL.
+#[derive(Debug, Clone)]
+pub struct PreparedRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    pub body: Option<Bytes>,
+}
+
+impl PreparedRequest {
+    pub async fn send(&self, client: &Client) -> reqwest::Result<Response> {
+        let mut request = client
+            .request(self.method.clone(), self.url.clone())
+            .headers(self.headers.clone());
+
+        if let Some(body) = &self.body {
+            request = request.body(body.clone());
+        }
+
+        request.send().await
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    load_tester::LoadTestConfig,
+    request::{PreparedRequest, RequestSpec},
+};
+use reqwest::Url;
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// One weighted entry of a traffic mix.
+///
+/// `deny_unknown_fields` does not work together with `flatten`, so fields
+/// neither this struct nor `RequestSpec` knows are collected in `unknown` and
+/// rejected by `TestPlan::resolve`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestTemplate {
+    pub name: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Path resolved against the target URL, e.g. `/api/search?q=rust`; the
+    /// target URL itself when omitted.
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub request: RequestSpec,
+    #[serde(flatten, skip_serializing)]
+    pub unknown: BTreeMap<String, IgnoredAny>,
+}
+
+/// Every field a scenario entry accepts, for unknown field errors.
+pub const REQUEST_TEMPLATE_FIELDS: &[&str] = &[
+    "name", "weight", "path", "method", "headers", "query", "body", "content_type",
+];
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// A request template resolved against one target.
+#[derive(Debug)]
+pub struct Endpoint {
+    pub name: String,
+    pub request: PreparedRequest,
+    cumulative_weight: u64,
+}
+
+/// The weighted set of requests a `LoadTest` sends to its target. Without a
+/// configured scenario it holds a single endpoint built from the config's
+/// request.
+#[derive(Debug)]
+pub struct Scenario {
+    endpoints: Vec<Endpoint>,
+    total_weight: u64,
+}
+
+impl Scenario {
+    pub fn prepare(config: &LoadTestConfig, target_url: &str) -> Result<Self, String> {
+        if config.scenario.is_empty() {
+            let spec = config
+                .request
+                .clone()
+                .unwrap_or_else(|| RequestSpec::for_url(target_url));
+            let request = spec.prepare(target_url)?;
+            let name = format!("{} {}", request.method, request.url.path());
+
+            return Ok(Self {
+                endpoints: vec![Endpoint {
+                    name,
+                    request,
+                    cumulative_weight: 1,
+                }],
+                total_weight: 1,
+            });
+        }
+
+        let base = Url::parse(target_url).map_err(|e| format!("Invalid URL {}: {}", target_url, e))?;
+        let mut names = HashSet::new();
+        let mut endpoints = Vec::with_capacity(config.scenario.len());
+        let mut total_weight = 0;
+
+        for template in &config.scenario {
+            if !names.insert(template.name.as_str()) {
+                return Err(format!("Duplicate scenario endpoint name: {}", template.name));
+            }
+
+            let url = match &template.path {
+                Some(path) => base
+                    .join(path)
+                    .map_err(|e| format!("Invalid path {} for endpoint {}: {}", path, template.name, e))?,
+                None => base.clone(),
+            };
+
+            total_weight += template.weight as u64;
+            endpoints.push(Endpoint {
+                name: template.name.clone(),
+                request: template.request.prepare(url.as_str())?,
+                cumulative_weight: total_weight,
+            });
+        }
+
+        if total_weight == 0 {
+            return Err("Scenario weights must not all be zero".to_string());
+        }
+
+        Ok(Self {
+            endpoints,
+            total_weight,
+        })
+    }
+
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    /// Picks an endpoint at random in proportion to its weight.
+    pub fn pick(&self) -> &Endpoint {
+        let roll = fastrand::u64(0..self.total_weight);
+        let index = self
+            .endpoints
+            .partition_point(|endpoint| endpoint.cumulative_weight <= roll);
+
+        &self.endpoints[index]
+    }
+}
@@ -0,0 +1,354 @@
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Bootstrap replicates drawn for every confidence interval.
+const BOOTSTRAP_RESAMPLES: usize = 1000;
+/// Largest resample drawn from a latency histogram. Bigger samples use an
+/// m-out-of-n bootstrap whose replicates are rescaled to the full size.
+const MAX_RESAMPLE_SIZE: usize = 10_000;
+/// Fixed so repeated analyses of the same data agree.
+const BOOTSTRAP_SEED: u64 = 0x5eed_cafe;
+
+/// Point estimate with a two-sided confidence interval.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Estimate {
+    pub value: f64,
+    pub ci_low: f64,
+    pub ci_high: f64,
+}
+
+impl Estimate {
+    /// Whether the interval lies entirely on one side of zero.
+    pub fn excludes_zero(&self) -> bool {
+        self.ci_low > 0.0 || self.ci_high < 0.0
+    }
+}
+
+/// Result of a two-sided Mann-Whitney U test of `a` against `b`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MannWhitney {
+    pub u: f64,
+    pub z: f64,
+    pub p_value: f64,
+    /// Probability that a random value from `a` is lower than one from `b`,
+    /// counting ties as half.
+    pub prob_lower: f64,
+}
+
+/// Recorded values of a histogram as a cumulative table to sample from.
+struct Distribution {
+    values: Vec<u64>,
+    cumulative: Vec<u64>,
+    total: u64,
+}
+
+impl Distribution {
+    fn new(histogram: &Histogram<u64>) -> Self {
+        let mut values = Vec::new();
+        let mut cumulative = Vec::new();
+        let mut total = 0;
+
+        for bucket in histogram.iter_recorded() {
+            total += bucket.count_at_value();
+            values.push(bucket.value_iterated_to());
+            cumulative.push(total);
+        }
+
+        Self {
+            values,
+            cumulative,
+            total,
+        }
+    }
+
+    fn draw(&self, rng: &mut fastrand::Rng) -> u64 {
+        let target = rng.u64(0..self.total);
+        let index = self.cumulative.partition_point(|&count| count <= target);
+        self.values[index]
+    }
+
+    /// Quantiles of one resample, rescaled toward `full` by `sqrt(m / n)`.
+    fn resample_quantiles(
+        &self,
+        rng: &mut fastrand::Rng,
+        quantiles: &[f64],
+        full: &[f64],
+        buf: &mut Vec<u64>,
+    ) -> Vec<f64> {
+        let size = (self.total as usize).min(MAX_RESAMPLE_SIZE);
+        let scale = (size as f64 / self.total as f64).sqrt();
+
+        buf.clear();
+        buf.extend((0..size).map(|_| self.draw(rng)));
+
+        quantiles
+            .iter()
+            .zip(full)
+            .map(|(&q, &full)| {
+                let rank = ((q * size as f64).ceil() as usize).clamp(1, size) - 1;
+                let (_, value, _) = buf.select_nth_unstable(rank);
+                full + (*value as f64 - full) * scale
+            })
+            .collect()
+    }
+}
+
+/// Relative difference in percent of `b`'s quantiles against `a`'s, with
+/// bootstrap confidence intervals, one estimate per quantile. `None` when
+/// either histogram is empty.
+pub fn quantile_diffs(
+    a: &Histogram<u64>,
+    b: &Histogram<u64>,
+    quantiles: &[f64],
+    confidence: f64,
+) -> Option<Vec<Estimate>> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+
+    let full_a: Vec<f64> = quantiles.iter().map(|&q| a.value_at_quantile(q) as f64).collect();
+    let full_b: Vec<f64> = quantiles.iter().map(|&q| b.value_at_quantile(q) as f64).collect();
+    let dist_a = Distribution::new(a);
+    let dist_b = Distribution::new(b);
+
+    let mut rng = fastrand::Rng::with_seed(BOOTSTRAP_SEED);
+    let mut buf = Vec::with_capacity(MAX_RESAMPLE_SIZE);
+    let mut replicates = vec![Vec::with_capacity(BOOTSTRAP_RESAMPLES); quantiles.len()];
+
+    for _ in 0..BOOTSTRAP_RESAMPLES {
+        let qa = dist_a.resample_quantiles(&mut rng, quantiles, &full_a, &mut buf);
+        let qb = dist_b.resample_quantiles(&mut rng, quantiles, &full_b, &mut buf);
+        for (i, (qa, qb)) in qa.into_iter().zip(qb).enumerate() {
+            if let Some(diff) = diff_percent(qb, qa) {
+                replicates[i].push(diff);
+            }
+        }
+    }
+
+    Some(
+        full_a
+            .iter()
+            .zip(&full_b)
+            .zip(replicates)
+            .map(|((&qa, &qb), replicates)| {
+                estimate(diff_percent(qb, qa).unwrap_or(0.0), replicates, confidence)
+            })
+            .collect(),
+    )
+}
+
+/// Relative difference in percent of the mean of `b` against the mean of
+/// `a`, with a bootstrap confidence interval. `None` with fewer than two
+/// samples on either side.
+pub fn mean_diff(a: &[f64], b: &[f64], confidence: f64) -> Option<Estimate> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let point = diff_percent(mean(b), mean(a))?;
+
+    let mut rng = fastrand::Rng::with_seed(BOOTSTRAP_SEED);
+    let mut resample_mean = |values: &[f64]| {
+        (0..values.len())
+            .map(|_| values[rng.usize(0..values.len())])
+            .sum::<f64>()
+            / values.len() as f64
+    };
+
+    let replicates = (0..BOOTSTRAP_RESAMPLES)
+        .filter_map(|_| {
+            let ma = resample_mean(a);
+            let mb = resample_mean(b);
+            diff_percent(mb, ma)
+        })
+        .collect();
+
+    Some(estimate(point, replicates, confidence))
+}
+
+/// Mann-Whitney U test computed on the histogram buckets, with the tie
+/// correction that bucketing makes necessary. `None` when either histogram
+/// is empty.
+pub fn mann_whitney(a: &Histogram<u64>, b: &Histogram<u64>) -> Option<MannWhitney> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+
+    let mut counts: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+    for bucket in a.iter_recorded() {
+        counts.entry(bucket.value_iterated_to()).or_default().0 += bucket.count_at_value();
+    }
+    for bucket in b.iter_recorded() {
+        counts.entry(bucket.value_iterated_to()).or_default().1 += bucket.count_at_value();
+    }
+
+    let n1 = a.len() as f64;
+    let n2 = b.len() as f64;
+    let n = n1 + n2;
+
+    // U counts the (a, b) pairs where a is lower, ties as half
+    let mut u = 0.0;
+    let mut b_below = 0.0;
+    let mut tie_term = 0.0;
+    for &(count_a, count_b) in counts.values() {
+        let (count_a, count_b) = (count_a as f64, count_b as f64);
+        u += count_a * ((n2 - b_below - count_b) + count_b / 2.0);
+        b_below += count_b;
+
+        let tied = count_a + count_b;
+        tie_term += tied * tied * tied - tied;
+    }
+
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_term / (n * (n - 1.0)));
+    let z = if variance > 0.0 {
+        (u - mean) / variance.sqrt()
+    } else {
+        0.0
+    };
+
+    Some(MannWhitney {
+        u,
+        z,
+        p_value: (2.0 * (1.0 - normal_cdf(z.abs()))).clamp(0.0, 1.0),
+        prob_lower: u / (n1 * n2),
+    })
+}
+
+fn estimate(value: f64, mut replicates: Vec<f64>, confidence: f64) -> Estimate {
+    if replicates.is_empty() {
+        return Estimate {
+            value,
+            ci_low: value,
+            ci_high: value,
+        };
+    }
+
+    replicates.sort_by(f64::total_cmp);
+    let alpha = (1.0 - confidence) / 2.0;
+    let at = |q: f64| replicates[((q * replicates.len() as f64) as usize).min(replicates.len() - 1)];
+
+    Estimate {
+        value,
+        ci_low: at(alpha),
+        ci_high: at(1.0 - alpha),
+    }
+}
+
+/// `value` relative to `baseline` in percent; `None` for a zero baseline.
+pub fn diff_percent(value: f64, baseline: f64) -> Option<f64> {
+    (baseline != 0.0).then(|| (value - baseline) / baseline * 100.0)
+}
+
+/// Standard normal CDF via the Abramowitz-Stegun 7.1.26 erf approximation
+/// (absolute error below 1.5e-7).
+fn normal_cdf(z: f64) -> f64 {
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - poly * (-x * x).exp();
+
+    if z >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(values: impl IntoIterator<Item = u64>) -> Histogram<u64> {
+        let mut histogram = Histogram::new(3).unwrap();
+        for value in values {
+            histogram.record(value).unwrap();
+        }
+        histogram
+    }
+
+    #[test]
+    fn mann_whitney_without_ties() {
+        // Every a is lower than every b: U = 3 * 3, z = 4.5 / sqrt(5.25)
+        let result = mann_whitney(&histogram([1, 2, 3]), &histogram([4, 5, 6])).unwrap();
+
+        assert_eq!(result.u, 9.0);
+        assert!((result.z - 1.963_961).abs() < 1e-6);
+        assert!((result.p_value - 0.049_535).abs() < 1e-5);
+        assert_eq!(result.prob_lower, 1.0);
+    }
+
+    #[test]
+    fn mann_whitney_with_ties() {
+        // U = 2 + 1.5 + 1.5; the three tied 2s shrink the variance to 2.4
+        let result = mann_whitney(&histogram([1, 2, 2]), &histogram([2, 3])).unwrap();
+
+        assert_eq!(result.u, 5.0);
+        assert!((result.z - 1.290_994).abs() < 1e-6);
+        assert!((result.p_value - 0.196_706).abs() < 1e-5);
+        assert!((result.prob_lower - 5.0 / 6.0).abs() < 1e-12);
+
+        let reversed = mann_whitney(&histogram([2, 3]), &histogram([1, 2, 2])).unwrap();
+        assert_eq!(reversed.u, 6.0 - result.u);
+        assert!((reversed.p_value - result.p_value).abs() < 1e-12);
+    }
+
+    #[test]
+    fn mann_whitney_needs_both_samples() {
+        assert!(mann_whitney(&histogram([1, 2]), &histogram([])).is_none());
+    }
+
+    #[test]
+    fn resample_is_rescaled_to_full_size() {
+        // 40 000 values resampled 10 000 at a time: scale sqrt(1 / 4) = 0.5,
+        // so a resampled median of 300 against the full 100 lands on 200
+        let histogram = histogram((0..40_000).map(|i| if i % 2 == 0 { 100 } else { 300 }));
+        let distribution = Distribution::new(&histogram);
+        let full = [histogram.value_at_quantile(0.5) as f64];
+        let mut rng = fastrand::Rng::with_seed(BOOTSTRAP_SEED);
+        let mut buf = Vec::new();
+
+        assert_eq!(full[0], 100.0);
+        for _ in 0..20 {
+            let median = distribution.resample_quantiles(&mut rng, &[0.5], &full, &mut buf)[0];
+            assert!(median == 100.0 || median == 200.0, "median {}", median);
+            assert_eq!(buf.len(), MAX_RESAMPLE_SIZE);
+        }
+    }
+
+    #[test]
+    fn quantile_diffs_of_identical_histograms() {
+        let a = histogram((0..5_000).map(|i| i % 1000 + 1));
+        let diffs = quantile_diffs(&a, &a, &[0.5, 0.99], 0.95).unwrap();
+
+        for estimate in &diffs {
+            assert_eq!(estimate.value, 0.0);
+            assert!(!estimate.excludes_zero());
+            assert!(estimate.ci_low > -5.0 && estimate.ci_high < 5.0, "{:?}", estimate);
+        }
+        assert!(quantile_diffs(&a, &histogram([]), &[0.5], 0.95).is_none());
+    }
+
+    #[test]
+    fn quantile_diffs_of_large_histograms() {
+        // Above MAX_RESAMPLE_SIZE, so resampled m out of n
+        let a = histogram((0..50_000).map(|i| i % 1000 + 1));
+        let doubled = histogram((0..50_000).map(|i| (i % 1000 + 1) * 2));
+        let diffs = quantile_diffs(&a, &doubled, &[0.5], 0.95).unwrap();
+
+        assert_eq!(diffs[0].value, 100.0);
+        assert!(diffs[0].ci_low > 90.0 && diffs[0].ci_high < 110.0, "{:?}", diffs[0]);
+    }
+
+    #[test]
+    fn mean_diff_of_constant_samples() {
+        let estimate = mean_diff(&[10.0, 10.0, 10.0], &[15.0, 15.0, 15.0], 0.95).unwrap();
+        assert_eq!((estimate.value, estimate.ci_low, estimate.ci_high), (50.0, 50.0, 50.0));
+
+        assert!(mean_diff(&[10.0], &[15.0, 15.0], 0.95).is_none());
+    }
+}
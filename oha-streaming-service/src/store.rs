@@ -0,0 +1,333 @@
+use crate::{
+    comparison::PairComparison,
+    load_tester::{LoadTestConfig, LoadTestResult, LoadTestStatus},
+    test_run::{Annotation, TargetSpec, TestRun},
+    threshold::ThresholdReport,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use hdrhistogram::{
+    serialization::{Serializer, V2DeflateSerializer},
+    Histogram,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+use tracing::warn;
+use uuid::Uuid;
+
+const DEFAULT_RESULTS_DIR: &str = "data/results";
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+
+/// A finished run as persisted on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredRun {
+    pub test_id: String,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+    pub status: LoadTestStatus,
+    pub targets: Vec<TargetSpec>,
+    pub config: LoadTestConfig,
+    pub results: Vec<LoadTestResult>,
+    /// Per-target success latency histograms (µs), pooled across trials,
+    /// V2+deflate encoded and base64'd so they can be reloaded for later
+    /// analysis.
+    pub histograms: HashMap<String, String>,
+    #[serde(default)]
+    pub comparisons: Vec<PairComparison>,
+    #[serde(default)]
+    pub thresholds: Option<ThresholdReport>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+impl StoredRun {
+    pub fn from_run(
+        run: &TestRun,
+        results: Vec<LoadTestResult>,
+        comparisons: Vec<PairComparison>,
+        thresholds: Option<ThresholdReport>,
+    ) -> Self {
+        let histograms = run
+            .target_samples()
+            .into_iter()
+            .filter_map(|samples| Some((samples.name, encode_histogram(&samples.latency)?)))
+            .collect();
+
+        Self {
+            test_id: run.test_id.clone(),
+            started_at: run.started_at,
+            completed_at: Utc::now(),
+            status: run.status(),
+            targets: run.specs.clone(),
+            config: run.config.clone(),
+            results,
+            histograms,
+            comparisons,
+            thresholds,
+            annotations: run.annotations(),
+        }
+    }
+
+    fn summary(&self) -> RunSummary {
+        RunSummary {
+            test_id: self.test_id.clone(),
+            started_at: self.started_at,
+            completed_at: self.completed_at,
+            status: self.status.clone(),
+            targets: self.targets.iter().map(|t| t.name.clone()).collect(),
+            duration_seconds: self.config.duration_seconds,
+            thresholds_passed: self.thresholds.as_ref().map(|report| report.passed),
+        }
+    }
+}
+
+/// Encodes a histogram for `StoredRun::histograms`.
+fn encode_histogram(histogram: &Histogram<u64>) -> Option<String> {
+    let mut buf = Vec::new();
+    match V2DeflateSerializer::new().serialize(histogram, &mut buf) {
+        Ok(_) => Some(STANDARD.encode(buf)),
+        Err(e) => {
+            warn!("Failed to serialize histogram: {:?}", e);
+            None
+        }
+    }
+}
+
+/// One entry of the history listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub test_id: String,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+    pub status: LoadTestStatus,
+    pub targets: Vec<String>,
+    pub duration_seconds: u64,
+    /// `None` when the run had no thresholds.
+    pub thresholds_passed: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryQuery {
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+    /// Only runs started at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Only runs started before this time.
+    pub to: Option<DateTime<Utc>>,
+    /// Only runs that included a target with this name.
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+    pub runs: Vec<RunSummary>,
+}
+
+/// File-based store of completed runs, one JSON document per run. The index
+/// of summaries is kept in memory, newest first.
+pub struct ResultStore {
+    dir: PathBuf,
+    index: RwLock<Vec<RunSummary>>,
+}
+
+impl ResultStore {
+    /// Opens the store in `RESULTS_DIR` (default `data/results`), creating the
+    /// directory and indexing any runs already in it.
+    pub fn open() -> io::Result<Self> {
+        let dir = std::env::var("RESULTS_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(DEFAULT_RESULTS_DIR));
+        fs::create_dir_all(&dir)?;
+
+        let mut index = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            match read_run(&path) {
+                Ok(run) => index.push(run.summary()),
+                Err(e) => warn!("Skipping unreadable result {}: {}", path.display(), e),
+            }
+        }
+        index.sort_by_key(|summary| std::cmp::Reverse(summary.started_at));
+
+        Ok(Self {
+            dir,
+            index: RwLock::new(index),
+        })
+    }
+
+    /// Writes the run atomically (temp file, then rename) and indexes it.
+    pub fn save(&self, run: &StoredRun) -> io::Result<()> {
+        let path = self.path_for(&run.test_id).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "test id is not a UUID")
+        })?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(run)?)?;
+        fs::rename(&tmp, &path)?;
+
+        let mut index = self.index.write().unwrap();
+        index.retain(|summary| summary.test_id != run.test_id);
+        let position = index
+            .iter()
+            .position(|summary| summary.started_at < run.started_at)
+            .unwrap_or(index.len());
+        index.insert(position, run.summary());
+
+        Ok(())
+    }
+
+    /// Loads a stored run. `Ok(None)` when no such run was saved.
+    pub fn get(&self, test_id: &str) -> io::Result<Option<StoredRun>> {
+        let Some(path) = self.path_for(test_id) else {
+            return Ok(None);
+        };
+
+        match read_run(&path) {
+            Ok(run) => Ok(Some(run)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn list(&self, query: &HistoryQuery) -> HistoryPage {
+        let page = query.page.unwrap_or(1).max(1);
+        let per_page = query
+            .per_page
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        let index = self.index.read().unwrap();
+        let matching: Vec<&RunSummary> = index
+            .iter()
+            .filter(|run| query.from.is_none_or(|from| run.started_at >= from))
+            .filter(|run| query.to.is_none_or(|to| run.started_at < to))
+            .filter(|run| {
+                query
+                    .target
+                    .as_ref()
+                    .is_none_or(|target| run.targets.contains(target))
+            })
+            .collect();
+
+        HistoryPage {
+            page,
+            per_page,
+            total: matching.len(),
+            runs: matching
+                .into_iter()
+                // A page far past the end is empty, not an overflow
+                .skip((page - 1).saturating_mul(per_page))
+                .take(per_page)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Only UUIDs map to a file, so ids from the URL cannot escape `dir`.
+    fn path_for(&self, test_id: &str) -> Option<PathBuf> {
+        let id = Uuid::parse_str(test_id).ok()?;
+        Some(self.dir.join(format!("{}.json", id)))
+    }
+}
+
+fn read_run(path: &Path) -> io::Result<StoredRun> {
+    let bytes = fs::read(path)?;
+    serde_json::from_slice(&bytes).map_err(io::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// A store indexing runs started at 00:00, 00:01, ... 00:0n; run `i`
+    /// targets "a" plus "b" for even `i`.
+    fn store(runs: u32) -> ResultStore {
+        let index = (0..runs)
+            .rev()
+            .map(|i| {
+                let started_at = Utc.with_ymd_and_hms(2024, 1, 1, 0, i, 0).unwrap();
+                RunSummary {
+                    test_id: format!("run-{}", i),
+                    started_at,
+                    completed_at: started_at,
+                    status: LoadTestStatus::Completed,
+                    targets: if i % 2 == 0 { vec!["a".into(), "b".into()] } else { vec!["a".into()] },
+                    duration_seconds: 10,
+                    thresholds_passed: None,
+                }
+            })
+            .collect();
+
+        ResultStore {
+            dir: PathBuf::new(),
+            index: RwLock::new(index),
+        }
+    }
+
+    fn ids(page: &HistoryPage) -> Vec<&str> {
+        page.runs.iter().map(|run| run.test_id.as_str()).collect()
+    }
+
+    fn query(page: usize, per_page: usize) -> HistoryQuery {
+        HistoryQuery {
+            page: Some(page),
+            per_page: Some(per_page),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pages_newest_first() {
+        let store = store(5);
+
+        let first = store.list(&query(1, 2));
+        assert_eq!((first.page, first.per_page, first.total), (1, 2, 5));
+        assert_eq!(ids(&first), ["run-4", "run-3"]);
+        assert_eq!(ids(&store.list(&query(3, 2))), ["run-0"]);
+        assert!(store.list(&query(4, 2)).runs.is_empty());
+    }
+
+    #[test]
+    fn clamps_out_of_range_pages() {
+        let store = store(5);
+
+        assert_eq!(ids(&store.list(&query(0, 0))), ["run-4"]);
+        assert_eq!(store.list(&query(1, 1000)).per_page, MAX_PAGE_SIZE);
+
+        let far = store.list(&query(usize::MAX, MAX_PAGE_SIZE));
+        assert_eq!(far.total, 5);
+        assert!(far.runs.is_empty());
+    }
+
+    #[test]
+    fn filters_by_target_and_time() {
+        let store = store(5);
+
+        let with_b = store.list(&HistoryQuery {
+            target: Some("b".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(ids(&with_b), ["run-4", "run-2", "run-0"]);
+
+        let window = store.list(&HistoryQuery {
+            from: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 1, 0).unwrap()),
+            to: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 3, 0).unwrap()),
+            ..Default::default()
+        });
+        assert_eq!(ids(&window), ["run-2", "run-1"]);
+    }
+}
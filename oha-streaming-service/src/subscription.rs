@@ -0,0 +1,190 @@
+use crate::StreamMessage;
+use serde::{Deserialize, Serialize};
+
+/// Which stream messages a WebSocket client wants. Empty filters match
+/// everything, so the default subscription receives every message of every
+/// test.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Only this test's messages; every test when omitted.
+    #[serde(default)]
+    pub test_id: Option<String>,
+    /// Only messages about these targets. Messages about the whole test,
+    /// such as `TestStarted`, are always included.
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// Only these message types, e.g. `Progress`.
+    #[serde(default)]
+    pub types: Vec<String>,
+}
+
+/// Query string of `/ws`; `target` and `types` are comma-separated lists.
+#[derive(Debug, Default, Deserialize)]
+pub struct SubscriptionQuery {
+    pub test_id: Option<String>,
+    pub target: Option<String>,
+    pub types: Option<String>,
+    /// Replay the logged messages after this sequence number first.
+    pub since: Option<u64>,
+}
+
+impl SubscriptionQuery {
+    pub fn into_subscription(self) -> Subscription {
+        Subscription {
+            test_id: self.test_id.filter(|id| !id.is_empty()),
+            targets: split_list(self.target.as_deref()),
+            types: split_list(self.types.as_deref()),
+        }
+    }
+}
+
+fn split_list(list: Option<&str>) -> Vec<String> {
+    list.into_iter()
+        .flat_map(|list| list.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl Subscription {
+    /// Rejects unknown message types, which would silently match nothing.
+    pub fn validate(&self) -> Result<(), String> {
+        match self
+            .types
+            .iter()
+            .find(|t| !StreamMessage::TYPES.contains(&t.as_str()))
+        {
+            Some(unknown) => Err(format!(
+                "unknown message type `{}` (expected one of {})",
+                unknown,
+                StreamMessage::TYPES.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn matches(&self, message: &StreamMessage) -> bool {
+        self.test_id
+            .as_deref()
+            .is_none_or(|id| message.test_id() == Some(id))
+            && (self.targets.is_empty()
+                || message.target().is_none_or(|target| self.targets.iter().any(|t| t == target)))
+            && (self.types.is_empty() || self.types.iter().any(|t| t == message.message_type()))
+    }
+}
+
+/// The subscriptions of one connection; a message is delivered when any of
+/// them matches. A connection starts with the single subscription its URL
+/// describes, which for a bare `/ws` matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct Subscriptions(Vec<Subscription>);
+
+impl Subscriptions {
+    pub fn new(initial: Subscription) -> Self {
+        Self(vec![initial])
+    }
+
+    /// Adds `subscription`, replacing any existing one for the same test
+    /// (or the catch-all one when it names no test).
+    pub fn subscribe(&mut self, subscription: Subscription) {
+        self.0.retain(|existing| existing.test_id != subscription.test_id);
+        self.0.push(subscription);
+    }
+
+    /// Drops the subscription for `test_id`, or every subscription when no
+    /// test is named.
+    pub fn unsubscribe(&mut self, test_id: Option<&str>) {
+        match test_id {
+            Some(test_id) => self.0.retain(|existing| existing.test_id.as_deref() != Some(test_id)),
+            None => self.0.clear(),
+        }
+    }
+
+    pub fn matches(&self, message: &StreamMessage) -> bool {
+        self.0.iter().any(|subscription| subscription.matches(message))
+    }
+
+    pub fn list(&self) -> &[Subscription] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(test_id: &str, runtime: &str) -> StreamMessage {
+        StreamMessage::TestError {
+            test_id: test_id.to_string(),
+            runtime: runtime.to_string(),
+            error: "connection refused".to_string(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    fn paused(test_id: &str) -> StreamMessage {
+        StreamMessage::TestPaused {
+            test_id: test_id.to_string(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    fn subscription(test_id: Option<&str>, target: &str, types: &str) -> Subscription {
+        SubscriptionQuery {
+            test_id: test_id.map(str::to_string),
+            target: Some(target.to_string()),
+            types: Some(types.to_string()),
+            since: None,
+        }
+        .into_subscription()
+    }
+
+    #[test]
+    fn default_matches_everything() {
+        let all = Subscription::default();
+        assert!(all.matches(&error("a", "tokio")));
+        assert!(all.matches(&paused("b")));
+    }
+
+    #[test]
+    fn filters_by_test_target_and_type() {
+        let sub = subscription(Some("a"), " tokio, ,rayon", "TestError,TestPaused");
+        assert_eq!(sub.targets, ["tokio", "rayon"]);
+
+        assert!(sub.matches(&error("a", "rayon")));
+        assert!(!sub.matches(&error("b", "rayon")));
+        assert!(!sub.matches(&error("a", "smol")));
+        // Messages about the whole test pass any target filter
+        assert!(sub.matches(&paused("a")));
+
+        let progress_only = subscription(None, "", "Progress");
+        assert!(!progress_only.matches(&error("a", "tokio")));
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        assert!(subscription(None, "", "Progress,TestPaused").validate().is_ok());
+        let err = subscription(None, "", "Progres").validate().unwrap_err();
+        assert!(err.contains("`Progres`"), "{}", err);
+    }
+
+    #[test]
+    fn any_subscription_delivers() {
+        let mut subs = Subscriptions::new(subscription(Some("a"), "", ""));
+        subs.subscribe(subscription(Some("b"), "tokio", ""));
+        assert!(subs.matches(&error("a", "smol")));
+        assert!(subs.matches(&error("b", "tokio")));
+        assert!(!subs.matches(&error("b", "smol")));
+
+        // Re-subscribing replaces the test's subscription
+        subs.subscribe(subscription(Some("b"), "smol", ""));
+        assert_eq!(subs.list().len(), 2);
+        assert!(subs.matches(&error("b", "smol")));
+
+        subs.unsubscribe(Some("a"));
+        assert!(!subs.matches(&error("a", "smol")));
+        subs.unsubscribe(None);
+        assert!(!subs.matches(&error("b", "smol")));
+    }
+}
@@ -0,0 +1,504 @@
+use crate::{
+    comparison::{self, PairComparison, TargetSamples},
+    event_bus::EventBus,
+    load_tester::{LoadTest, LoadTestConfig, LoadTestResult, LoadTestStatus},
+    pause::PauseSwitch,
+    plan::PlanChange,
+    request::RequestSpec,
+    threshold::{self, CompiledThreshold, ThresholdOutcome, ThresholdReport},
+    trial::TrialExecution,
+    StreamMessage,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::time::interval;
+use tracing::warn;
+
+/// How often a cooldown between trials checks whether the run was stopped.
+const COOLDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A named endpoint to load-test, e.g. one runtime build.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetSpec {
+    pub name: String,
+    pub url: String,
+    /// Overrides the test-wide request for this target only.
+    #[serde(default)]
+    pub request: Option<RequestSpec>,
+}
+
+impl TargetSpec {
+    /// The test-wide config with this target's request resolved into it.
+    pub fn config(&self, config: &LoadTestConfig) -> LoadTestConfig {
+        LoadTestConfig {
+            request: Some(self.request_spec(config)),
+            ..config.clone()
+        }
+    }
+
+    /// The request this target sends: its own override, else the test-wide
+    /// request, else the defaults for its URL.
+    pub fn request_spec(&self, config: &LoadTestConfig) -> RequestSpec {
+        self.request
+            .clone()
+            .or_else(|| config.request.clone())
+            .unwrap_or_else(|| RequestSpec::for_url(&self.url))
+    }
+}
+
+/// A note on a run's timeline, made for every change of rate or connections
+/// while it runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    /// Seconds since the run started, not counting time spent paused.
+    pub elapsed_seconds: f64,
+    /// The new rate, when it changed.
+    pub rate_per_second: Option<u64>,
+    /// The new connection count, when it changed.
+    pub connections: Option<u64>,
+    pub note: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// What `TestRun::execute` produced.
+#[derive(Debug, Clone)]
+pub struct RunOutcome {
+    pub results: Vec<LoadTestResult>,
+    pub comparisons: Vec<PairComparison>,
+    pub thresholds: Option<ThresholdReport>,
+}
+
+/// All targets of one comparison run, each driven by its own `LoadTest`
+/// with the shared config. With trials, `targets` holds one `LoadTest` per
+/// trial of every target, in execution order.
+#[derive(Debug, Clone)]
+pub struct TestRun {
+    pub test_id: String,
+    pub config: LoadTestConfig,
+    pub started_at: DateTime<Utc>,
+    pub specs: Vec<TargetSpec>,
+    pub targets: Vec<LoadTest>,
+    /// Statistical comparison of the targets, set once the run has been
+    /// analyzed and saved.
+    pub comparisons: Arc<Mutex<Option<Vec<PairComparison>>>>,
+    pub thresholds: Vec<CompiledThreshold>,
+    /// Set when an `abort_on_fail` threshold stopped the run.
+    pub aborted: Arc<AtomicBool>,
+    pub threshold_report: Arc<Mutex<Option<ThresholdReport>>>,
+    /// Paused as a whole; each target also has its own switch, which
+    /// `stop_target` can release on its own.
+    pub pause: PauseSwitch,
+    /// Live changes made so far, oldest first.
+    pub annotations: Arc<Mutex<Vec<Annotation>>>,
+    pub events: EventBus,
+}
+
+impl TestRun {
+    /// Fails when a threshold does not parse or names an unknown target.
+    pub fn new(
+        test_id: String,
+        targets: &[TargetSpec],
+        config: LoadTestConfig,
+        events: EventBus,
+    ) -> Result<Self, String> {
+        let names: Vec<&str> = targets.iter().map(|target| target.name.as_str()).collect();
+        let thresholds = threshold::compile_all(&config.thresholds, &names)?;
+
+        let specs = targets.to_vec();
+        let trials = config.trials;
+        let targets = trials
+            .schedule(specs.len())
+            .into_iter()
+            .flatten()
+            .map(|(trial, index)| {
+                let target = &specs[index];
+                LoadTest::new(
+                    test_id.clone(),
+                    target.name.clone(),
+                    target.url.clone(),
+                    trials.enabled().then_some(trial),
+                    target.config(&config),
+                    events.clone(),
+                )
+            })
+            .collect();
+
+        Ok(Self {
+            test_id,
+            config,
+            started_at: Utc::now(),
+            specs,
+            targets,
+            comparisons: Arc::new(Mutex::new(None)),
+            thresholds,
+            aborted: Arc::new(AtomicBool::new(false)),
+            threshold_report: Arc::new(Mutex::new(None)),
+            pause: PauseSwitch::new(),
+            annotations: Arc::new(Mutex::new(Vec::new())),
+            events,
+        })
+    }
+
+    /// Runs the whole test: announces it, runs every trial, evaluates the
+    /// thresholds and compares the targets, broadcasting each step. The run
+    /// is not finished until `finish` is called with the comparisons.
+    pub async fn execute(&self) -> RunOutcome {
+        self.events.publish(StreamMessage::TestStarted {
+            test_id: self.test_id.clone(),
+            targets: self.specs.clone(),
+            config: self.config.clone(),
+            timestamp: Utc::now(),
+        });
+
+        let results = self.run().await;
+        let thresholds = self.evaluate_thresholds(&results);
+        let comparisons = self.analyze().await;
+
+        if !comparisons.is_empty() {
+            self.events.publish(StreamMessage::ComparisonCompleted {
+                test_id: self.test_id.clone(),
+                comparisons: comparisons.clone(),
+                timestamp: Utc::now(),
+            });
+        }
+
+        RunOutcome {
+            results,
+            comparisons,
+            thresholds,
+        }
+    }
+
+    /// Marks the run finished, making its results available and letting
+    /// cleanup drop it.
+    pub fn finish(&self, comparisons: Vec<PairComparison>) {
+        *self.comparisons.lock().unwrap() = Some(comparisons);
+    }
+
+    /// Runs every trial, a round of targets at a time (or one trial at a
+    /// time when sequential), and returns the results in execution order.
+    /// Trials stopped before they started are skipped.
+    pub async fn run(&self) -> Vec<LoadTestResult> {
+        let batch_size = match self.config.trials.execution {
+            TrialExecution::Concurrent => self.specs.len().max(1),
+            TrialExecution::Sequential => 1,
+        };
+        let cooldown = Duration::from_secs(self.config.trials.cooldown_seconds);
+        let monitor = self.monitor_thresholds();
+
+        let mut results = Vec::new();
+        for (i, batch) in self.targets.chunks(batch_size).enumerate() {
+            let mut batch: Vec<LoadTest> = batch.iter().filter(|test| !test.stop_requested()).cloned().collect();
+            if batch.is_empty() {
+                continue;
+            }
+            if i > 0 && !cooldown.is_zero() {
+                self.cool_down(cooldown, &batch).await;
+                // Targets stopped during the cooldown do not start
+                batch.retain(|test| !test.stop_requested());
+            }
+
+            let handles: Vec<_> = batch
+                .into_iter()
+                .map(|test| tokio::spawn(async move { test.run().await }))
+                .collect();
+
+            results.extend(
+                futures_util::future::join_all(handles)
+                    .await
+                    .into_iter()
+                    .filter_map(Result::ok),
+            );
+        }
+
+        if let Some(monitor) = monitor {
+            monitor.abort();
+        }
+
+        results
+    }
+
+    /// Waits `cooldown` before `batch` starts. Time spent paused does not
+    /// count, and the wait ends early once every test of the batch has been
+    /// stopped.
+    async fn cool_down(&self, cooldown: Duration, batch: &[LoadTest]) {
+        let mut remaining = cooldown;
+
+        while !remaining.is_zero() && batch.iter().any(|test| !test.stop_requested()) {
+            // Stopping the run resumes it, so this cannot outlast a stop
+            self.pause.resumed().await;
+
+            let step_started = Instant::now();
+            tokio::select! {
+                _ = tokio::time::sleep(remaining.min(COOLDOWN_POLL_INTERVAL)) => {}
+                _ = self.pause.paused() => {}
+            }
+            remaining = remaining.saturating_sub(step_started.elapsed());
+        }
+    }
+
+    /// Evaluates the `abort_on_fail` thresholds every second against the
+    /// results so far and stops the whole run on the first breach. `None`
+    /// when no threshold aborts.
+    fn monitor_thresholds(&self) -> Option<tokio::task::JoinHandle<()>> {
+        if !self.thresholds.iter().any(|t| t.threshold.abort_on_fail) {
+            return None;
+        }
+
+        let mut run = self.clone();
+        Some(tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(1));
+            // The first tick is immediate, before any traffic
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+                if run.pause.is_paused() {
+                    continue;
+                }
+
+                let elapsed = run.elapsed_seconds();
+                let due: Vec<&CompiledThreshold> = run
+                    .thresholds
+                    .iter()
+                    .filter(|t| t.threshold.abort_on_fail && elapsed >= t.threshold.abort_grace_seconds as f64)
+                    .collect();
+                if due.is_empty() {
+                    continue;
+                }
+
+                let snapshot = run.snapshot();
+                let outcomes: Vec<_> = due.iter().map(|t| t.evaluate(&snapshot)).collect();
+                // Targets still waiting for their trial have no data yet
+                if !outcomes.iter().any(ThresholdOutcome::breached) {
+                    continue;
+                }
+
+                warn!("Aborting test {}: threshold breached", run.test_id);
+                run.aborted.store(true, Ordering::Relaxed);
+                run.events.publish(StreamMessage::ThresholdEvaluated {
+                    test_id: run.test_id.clone(),
+                    report: ThresholdReport {
+                        passed: false,
+                        aborted: true,
+                        thresholds: outcomes,
+                    },
+                    timestamp: Utc::now(),
+                });
+                run.stop();
+                break;
+            }
+        }))
+    }
+
+    /// Final results of finished trials plus live results of running ones
+    /// that have started measuring (nothing is recorded during warm-up).
+    fn snapshot(&self) -> Vec<LoadTestResult> {
+        self.targets
+            .iter()
+            .filter_map(|test| match test.status() {
+                LoadTestStatus::Running | LoadTestStatus::Paused => {
+                    (test.requests_sent.load(Ordering::Relaxed) > 0).then(|| test.snapshot())
+                }
+                _ => test.result(),
+            })
+            .collect()
+    }
+
+    /// Evaluates every threshold against the final results, broadcasts the
+    /// outcome and keeps it on the run. `None` without thresholds.
+    pub fn evaluate_thresholds(&self, results: &[LoadTestResult]) -> Option<ThresholdReport> {
+        if self.thresholds.is_empty() {
+            return None;
+        }
+
+        let report = threshold::evaluate_all(&self.thresholds, results, self.aborted.load(Ordering::Relaxed));
+        self.events.publish(StreamMessage::ThresholdEvaluated {
+            test_id: self.test_id.clone(),
+            report: report.clone(),
+            timestamp: Utc::now(),
+        });
+        *self.threshold_report.lock().unwrap() = Some(report.clone());
+
+        Some(report)
+    }
+
+    /// Compares every pair of targets on their recorded samples.
+    pub async fn analyze(&self) -> Vec<PairComparison> {
+        let samples = self.target_samples();
+
+        tokio::task::spawn_blocking(move || comparison::compare_targets(&samples))
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Samples of every target, pooled across its trials.
+    pub fn target_samples(&self) -> Vec<TargetSamples> {
+        self.specs
+            .iter()
+            .map(|spec| {
+                let mut latency = self.config.histogram.new_histogram();
+                let mut throughput = Vec::new();
+
+                for test in self.targets.iter().filter(|test| test.runtime == spec.name) {
+                    if let Err(e) = latency.add(&*test.latency_histogram.lock().unwrap()) {
+                        warn!("Failed to pool latency of {}: {}", spec.name, e);
+                    }
+                    throughput.extend(test.throughput_samples.lock().unwrap().iter().copied());
+                }
+
+                TargetSamples {
+                    name: spec.name.clone(),
+                    latency,
+                    throughput,
+                }
+            })
+            .collect()
+    }
+
+    pub fn comparisons(&self) -> Option<Vec<PairComparison>> {
+        self.comparisons.lock().unwrap().clone()
+    }
+
+    /// Stops every trial of the named target. `false` when there is no such
+    /// target.
+    pub fn stop_target(&mut self, name: &str) -> bool {
+        let mut found = false;
+        for test in self.targets.iter_mut().filter(|test| test.runtime == name) {
+            test.stop();
+            found = true;
+        }
+        found
+    }
+
+    /// Overall status: running (or paused) while any target runs or waits
+    /// for its trial, otherwise the most notable terminal state across
+    /// targets.
+    pub fn status(&self) -> LoadTestStatus {
+        let statuses: Vec<LoadTestStatus> = self.targets.iter().map(LoadTest::status).collect();
+
+        if statuses.iter().any(|s| {
+            matches!(
+                s,
+                LoadTestStatus::Running | LoadTestStatus::Paused | LoadTestStatus::Pending
+            )
+        }) {
+            if self.pause.is_paused() {
+                LoadTestStatus::Paused
+            } else {
+                LoadTestStatus::Running
+            }
+        } else if statuses.iter().any(|s| matches!(s, LoadTestStatus::Failed)) {
+            LoadTestStatus::Failed
+        } else if statuses.iter().any(|s| matches!(s, LoadTestStatus::Stopped)) {
+            LoadTestStatus::Stopped
+        } else {
+            LoadTestStatus::Completed
+        }
+    }
+
+    /// Every trial has finished or been skipped and the run has been
+    /// analyzed and saved.
+    pub fn is_finished(&self) -> bool {
+        self.comparisons.lock().unwrap().is_some()
+    }
+
+    /// Results of every trial that ran, in execution order.
+    pub fn results(&self) -> Vec<LoadTestResult> {
+        self.targets.iter().filter_map(LoadTest::result).collect()
+    }
+
+    /// Time since the run started, not counting time spent paused.
+    pub fn elapsed_seconds(&self) -> f64 {
+        let elapsed = (Utc::now() - self.started_at).to_std().unwrap_or_default();
+        elapsed.saturating_sub(self.pause.paused_time()).as_secs_f64()
+    }
+
+    /// Parks the workers of every target, keeping their connections open,
+    /// until `resume`. Targets that start while paused wait too.
+    pub fn pause(&self) -> Result<(), String> {
+        match self.status() {
+            LoadTestStatus::Running => {}
+            LoadTestStatus::Paused => return Err("the test is already paused".to_string()),
+            status => return Err(format!("the test is not running ({:?})", status)),
+        }
+        if !self.pause.pause() {
+            return Err("the test is already paused".to_string());
+        }
+
+        for test in &self.targets {
+            test.pause.pause();
+        }
+        self.events.publish(StreamMessage::TestPaused {
+            test_id: self.test_id.clone(),
+            timestamp: Utc::now(),
+        });
+        Ok(())
+    }
+
+    /// Lets every target carry on where it was paused.
+    pub fn resume(&self) -> Result<(), String> {
+        let paused_for = self
+            .pause
+            .resume()
+            .ok_or_else(|| "the test is not paused".to_string())?;
+
+        for test in &self.targets {
+            test.pause.resume();
+        }
+        self.events.publish(StreamMessage::TestResumed {
+            test_id: self.test_id.clone(),
+            paused_seconds: paused_for.as_secs_f64(),
+            timestamp: Utc::now(),
+        });
+        Ok(())
+    }
+
+    /// Applies a rate and/or connection change to every target from now on,
+    /// including trials that have not started yet, and annotates the
+    /// timeline with it.
+    pub fn reconfigure(&self, change: PlanChange) -> Result<Annotation, String> {
+        match self.status() {
+            LoadTestStatus::Running | LoadTestStatus::Paused => {}
+            status => return Err(format!("the test is not running ({:?})", status)),
+        }
+
+        for test in &self.targets {
+            test.reconfigure(change.rate_per_second, change.connections);
+        }
+
+        let annotation = Annotation {
+            elapsed_seconds: self.elapsed_seconds(),
+            rate_per_second: change.rate_per_second,
+            connections: change.connections,
+            note: change.note,
+            timestamp: Utc::now(),
+        };
+        self.annotations.lock().unwrap().push(annotation.clone());
+        self.events.publish(StreamMessage::Annotation {
+            test_id: self.test_id.clone(),
+            annotation: annotation.clone(),
+        });
+
+        Ok(annotation)
+    }
+
+    pub fn annotations(&self) -> Vec<Annotation> {
+        self.annotations.lock().unwrap().clone()
+    }
+
+    pub fn stop(&mut self) {
+        for test in &mut self.targets {
+            test.stop();
+        }
+        self.pause.resume();
+    }
+}
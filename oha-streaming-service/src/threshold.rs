@@ -0,0 +1,646 @@
+use crate::{comparison::error_rate_percent, load_tester::LoadTestResult};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A pass/fail assertion on the results, e.g. `p99 < 200ms`,
+/// `error_rate < 0.5%` or `bun.p95 <= node.p95 * 1.1`. Accepts a bare
+/// expression string as shorthand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ThresholdInput")]
+pub struct Threshold {
+    pub expression: String,
+    /// Also evaluate every second while the run is in progress and stop the
+    /// whole run on the first breach.
+    pub abort_on_fail: bool,
+    /// Seconds into the run before continuous evaluation may abort, so
+    /// early, noisy percentiles do not end a run.
+    pub abort_grace_seconds: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "expected an expression string, or an object with `expression` and optional `abort_on_fail` and `abort_grace_seconds`"
+)]
+enum ThresholdInput {
+    Expression(String),
+    Full {
+        expression: String,
+        #[serde(default)]
+        abort_on_fail: bool,
+        #[serde(default)]
+        abort_grace_seconds: u64,
+    },
+}
+
+impl From<ThresholdInput> for Threshold {
+    fn from(input: ThresholdInput) -> Self {
+        match input {
+            ThresholdInput::Expression(expression) => Self {
+                expression,
+                abort_on_fail: false,
+                abort_grace_seconds: 0,
+            },
+            ThresholdInput::Full {
+                expression,
+                abort_on_fail,
+                abort_grace_seconds,
+            } => Self {
+                expression,
+                abort_on_fail,
+                abort_grace_seconds,
+            },
+        }
+    }
+}
+
+/// Outcome of every threshold of a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdReport {
+    pub passed: bool,
+    /// The run was stopped early because an `abort_on_fail` threshold failed.
+    pub aborted: bool,
+    pub thresholds: Vec<ThresholdOutcome>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdOutcome {
+    pub expression: String,
+    pub passed: bool,
+    /// One check per target for expressions with unqualified metrics such as
+    /// `p99 < 200ms`, otherwise a single check.
+    pub checks: Vec<ThresholdCheck>,
+}
+
+/// A check without data to compare, because a target has no results or no
+/// successful response to take a latency from, has `null` values and fails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdCheck {
+    pub target: Option<String>,
+    pub actual: Option<f64>,
+    pub limit: Option<f64>,
+    pub passed: bool,
+}
+
+impl ThresholdOutcome {
+    /// A check failed on actual values, as opposed to missing data, which
+    /// is still expected while the run is in progress.
+    pub fn breached(&self) -> bool {
+        self.checks
+            .iter()
+            .any(|check| !check.passed && check.actual.is_some() && check.limit.is_some())
+    }
+}
+
+/// A threshold parsed and checked against the run's target names.
+#[derive(Debug, Clone)]
+pub struct CompiledThreshold {
+    pub threshold: Threshold,
+    /// Every target of the run, which bare metrics are checked for.
+    targets: Vec<String>,
+    lhs: Expr,
+    op: Comparison,
+    rhs: Expr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    Rps,
+    Avg,
+    Min,
+    Max,
+    P50,
+    P95,
+    P99,
+    ErrorRate,
+    Errors,
+    Requests,
+}
+
+impl Metric {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "rps" => Metric::Rps,
+            "avg" => Metric::Avg,
+            "min" => Metric::Min,
+            "max" => Metric::Max,
+            "p50" | "median" => Metric::P50,
+            "p95" => Metric::P95,
+            "p99" => Metric::P99,
+            "error_rate" => Metric::ErrorRate,
+            "errors" => Metric::Errors,
+            "requests" => Metric::Requests,
+            _ => return None,
+        })
+    }
+
+    /// Latencies in milliseconds, `error_rate` as a fraction. Latencies are
+    /// `None` when no request succeeded, as there is nothing to measure.
+    fn value(self, result: &LoadTestResult) -> Option<f64> {
+        let latency = |us: f64| (result.successful_requests > 0).then_some(us / 1000.0);
+        match self {
+            Metric::Rps => Some(result.requests_per_second),
+            Metric::Avg => latency(result.avg_latency_us),
+            Metric::Min => latency(result.min_latency_us as f64),
+            Metric::Max => latency(result.max_latency_us as f64),
+            Metric::P50 => latency(result.p50_latency_us as f64),
+            Metric::P95 => latency(result.p95_latency_us as f64),
+            Metric::P99 => latency(result.p99_latency_us as f64),
+            Metric::ErrorRate => Some(error_rate_percent(result) / 100.0),
+            Metric::Errors => Some(result.failed_requests as f64),
+            Metric::Requests => Some(result.total_requests as f64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+        }
+    }
+
+    fn holds(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+            Comparison::Eq => (lhs - rhs).abs() < f64::EPSILON,
+            Comparison::Ne => (lhs - rhs).abs() >= f64::EPSILON,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(f64),
+    Metric { target: Option<String>, metric: Metric },
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+impl Expr {
+    fn has_unqualified(&self) -> bool {
+        match self {
+            Expr::Number(_) => false,
+            Expr::Metric { target, .. } => target.is_none(),
+            Expr::Binary(lhs, _, rhs) => lhs.has_unqualified() || rhs.has_unqualified(),
+        }
+    }
+
+    fn targets<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Metric { target, .. } => out.extend(target.as_deref()),
+            Expr::Binary(lhs, _, rhs) => {
+                lhs.targets(out);
+                rhs.targets(out);
+            }
+        }
+    }
+
+    /// `None` when a referenced target has no results, or a trial of it has
+    /// no value for the metric.
+    fn eval(&self, results: &[LoadTestResult], default_target: Option<&str>) -> Option<f64> {
+        match self {
+            Expr::Number(value) => Some(*value),
+            Expr::Metric { target, metric } => {
+                let target = target.as_deref().or(default_target)?;
+                let values = results
+                    .iter()
+                    .filter(|result| result.runtime == target)
+                    .map(|result| metric.value(result))
+                    .collect::<Option<Vec<f64>>>()?;
+                // Mean across trials
+                (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let (lhs, rhs) = (lhs.eval(results, default_target)?, rhs.eval(results, default_target)?);
+                Some(match op {
+                    '+' => lhs + rhs,
+                    '-' => lhs - rhs,
+                    '*' => lhs * rhs,
+                    _ => lhs / rhs,
+                })
+            }
+        }
+    }
+}
+
+impl CompiledThreshold {
+    /// Parses the expression and checks that every target it names exists.
+    pub fn compile(threshold: &Threshold, targets: &[&str]) -> Result<Self, String> {
+        let error = |e: String| format!("threshold `{}`: {}", threshold.expression, e);
+        let mut parser = Parser::new(&threshold.expression).map_err(error)?;
+        let (lhs, op, rhs) = parser.threshold().map_err(error)?;
+
+        let mut referenced = Vec::new();
+        lhs.targets(&mut referenced);
+        rhs.targets(&mut referenced);
+        if let Some(unknown) = referenced.iter().find(|name| !targets.contains(name)) {
+            return Err(error(format!("unknown target `{}`", unknown)));
+        }
+
+        Ok(Self {
+            threshold: threshold.clone(),
+            targets: targets.iter().map(|name| name.to_string()).collect(),
+            lhs,
+            op,
+            rhs,
+        })
+    }
+
+    /// Checks the threshold against whatever results are available, once
+    /// per target for bare metrics. Checks that lack data fail, so a target
+    /// that never ran or never got a successful response cannot pass.
+    pub fn evaluate(&self, results: &[LoadTestResult]) -> ThresholdOutcome {
+        let targets: Vec<Option<&str>> = if self.lhs.has_unqualified() || self.rhs.has_unqualified() {
+            self.targets.iter().map(|name| Some(name.as_str())).collect()
+        } else {
+            vec![None]
+        };
+
+        let checks: Vec<ThresholdCheck> = targets
+            .into_iter()
+            .map(|target| {
+                let actual = self.lhs.eval(results, target);
+                let limit = self.rhs.eval(results, target);
+                ThresholdCheck {
+                    target: target.map(str::to_string),
+                    actual,
+                    limit,
+                    passed: match (actual, limit) {
+                        (Some(actual), Some(limit)) => self.op.holds(actual, limit),
+                        _ => false,
+                    },
+                }
+            })
+            .collect();
+
+        ThresholdOutcome {
+            expression: self.threshold.expression.clone(),
+            passed: checks.iter().all(|check| check.passed),
+            checks,
+        }
+    }
+}
+
+/// Compiles every threshold of a run, failing on the first invalid one.
+pub fn compile_all(thresholds: &[Threshold], targets: &[&str]) -> Result<Vec<CompiledThreshold>, String> {
+    thresholds
+        .iter()
+        .map(|threshold| CompiledThreshold::compile(threshold, targets))
+        .collect()
+}
+
+/// Evaluates every threshold against the final results.
+pub fn evaluate_all(thresholds: &[CompiledThreshold], results: &[LoadTestResult], aborted: bool) -> ThresholdReport {
+    let outcomes: Vec<ThresholdOutcome> = thresholds.iter().map(|t| t.evaluate(results)).collect();
+
+    ThresholdReport {
+        passed: !aborted && outcomes.iter().all(|outcome| outcome.passed),
+        aborted,
+        thresholds: outcomes,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    /// A target name in quotes, for names that are not plain identifiers.
+    Quoted(String),
+    Dot,
+    Op(char),
+    Cmp(Comparison),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Quoted(name) => write!(f, "\"{}\"", name),
+            Token::Dot => write!(f, "."),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::Cmp(cmp) => write!(f, "{}", cmp.symbol()),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Result<Self, String> {
+        Ok(Self {
+            tokens: tokenize(input)?,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn threshold(&mut self) -> Result<(Expr, Comparison, Expr), String> {
+        let lhs = self.sum()?;
+        let op = match self.next() {
+            Some(Token::Cmp(op)) => op,
+            Some(token) => return Err(format!("expected a comparison, found `{}`", token)),
+            None => return Err("expected a comparison such as `<` or `>=`".to_string()),
+        };
+        let rhs = self.sum()?;
+
+        match self.peek() {
+            Some(token) => Err(format!("unexpected `{}`", token)),
+            None => Ok((lhs, op, rhs)),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.atom()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.pos += 1;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.atom()?));
+        }
+        Ok(expr)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Open) => {
+                let expr = self.sum()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("expected `)`".to_string()),
+                }
+            }
+            Some(Token::Quoted(name)) => match (self.next(), self.next()) {
+                (Some(Token::Dot), Some(Token::Ident(metric))) => Ok(Expr::Metric {
+                    target: Some(name),
+                    metric: parse_metric(&metric)?,
+                }),
+                _ => Err(format!("expected a metric after `\"{}\".`", name)),
+            },
+            Some(Token::Ident(name)) => {
+                if self.peek() == Some(&Token::Dot) {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(Token::Ident(metric)) => Ok(Expr::Metric {
+                            target: Some(name),
+                            metric: parse_metric(&metric)?,
+                        }),
+                        _ => Err(format!("expected a metric after `{}.`", name)),
+                    }
+                } else {
+                    Ok(Expr::Metric {
+                        target: None,
+                        metric: parse_metric(&name)?,
+                    })
+                }
+            }
+            Some(token) => Err(format!("unexpected `{}`", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn parse_metric(name: &str) -> Result<Metric, String> {
+    Metric::parse(name).ok_or_else(|| {
+        format!(
+            "unknown metric `{}` (expected rps, avg, min, max, p50, p95, p99, error_rate, errors or requests)",
+            name
+        )
+    })
+}
+
+/// Numbers may carry a unit: `us`, `ms` and `s` convert to milliseconds and
+/// `%` to a fraction. Identifiers are letters, digits and `_`; target names
+/// with other characters are written in single or double quotes.
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' => i += 1,
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            '.' if !chars.get(i + 1).is_some_and(char::is_ascii_digit) => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            '+' | '-' | '*' | '/' => {
+                tokens.push(Token::Op(c));
+                i += 1;
+            }
+            '<' | '>' | '=' | '!' => {
+                let eq = chars.get(i + 1) == Some(&'=');
+                let op = match (c, eq) {
+                    ('<', false) => Comparison::Lt,
+                    ('<', true) => Comparison::Le,
+                    ('>', false) => Comparison::Gt,
+                    ('>', true) => Comparison::Ge,
+                    ('=', true) => Comparison::Eq,
+                    ('!', true) => Comparison::Ne,
+                    _ => return Err(format!("unexpected `{}`", c)),
+                };
+                tokens.push(Token::Cmp(op));
+                i += if eq { 2 } else { 1 };
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                let value: f64 = number
+                    .parse()
+                    .map_err(|_| format!("invalid number `{}`", number))?;
+
+                let unit_start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphabetic() || chars[i] == '%') {
+                    i += 1;
+                }
+                let unit: String = chars[unit_start..i].iter().collect();
+                let scale = match unit.as_str() {
+                    "" | "ms" => 1.0,
+                    "us" => 0.001,
+                    "s" => 1000.0,
+                    "%" => 0.01,
+                    _ => return Err(format!("unknown unit `{}`", unit)),
+                };
+                tokens.push(Token::Number(value * scale));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            '"' | '\'' => {
+                let start = i + 1;
+                let end = chars[start..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .map(|len| start + len)
+                    .ok_or_else(|| format!("unterminated target name, expected a closing {}", c))?;
+                tokens.push(Token::Quoted(chars[start..end].iter().collect()));
+                i = end + 1;
+            }
+            _ => return Err(format!("unexpected `{}`", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn threshold(expression: &str) -> Threshold {
+        Threshold {
+            expression: expression.to_string(),
+            abort_on_fail: false,
+            abort_grace_seconds: 0,
+        }
+    }
+
+    fn compile(expression: &str, targets: &[&str]) -> Result<CompiledThreshold, String> {
+        CompiledThreshold::compile(&threshold(expression), targets)
+    }
+
+    /// A result of 100 successful requests with the given percentiles in ms.
+    fn result(runtime: &str, p50: u64, p95: u64, p99: u64) -> LoadTestResult {
+        LoadTestResult {
+            runtime: runtime.to_string(),
+            trial: None,
+            total_requests: 100,
+            successful_requests: 100,
+            failed_requests: 0,
+            total_duration_seconds: 1.0,
+            requests_per_second: 100.0,
+            avg_latency_ms: p50 as f64,
+            min_latency_ms: 0.0,
+            max_latency_ms: p99 as f64,
+            p50_latency_ms: p50 as f64,
+            p95_latency_ms: p95 as f64,
+            p99_latency_ms: p99 as f64,
+            avg_latency_us: (p50 * 1000) as f64,
+            min_latency_us: 0,
+            max_latency_us: p99 * 1000,
+            p50_latency_us: p50 * 1000,
+            p95_latency_us: p95 * 1000,
+            p99_latency_us: p99 * 1000,
+            late_requests: 0,
+            error_types: Default::default(),
+            endpoints: Default::default(),
+            latency_by_status_class: Default::default(),
+            phases: None,
+        }
+    }
+
+    #[test]
+    fn minus_between_metrics_is_a_subtraction() {
+        let compiled = compile("p99-p50 < 5", &["a", "b"]).unwrap();
+        let outcome = compiled.evaluate(&[result("a", 3, 6, 7), result("b", 3, 6, 9)]);
+
+        let checks: Vec<_> = outcome.checks.iter().map(|c| (c.target.as_deref(), c.actual, c.passed)).collect();
+        assert_eq!(checks, [(Some("a"), Some(4.0), true), (Some("b"), Some(6.0), false)]);
+        assert!(!outcome.passed);
+    }
+
+    #[test]
+    fn compares_targets_against_each_other() {
+        let compiled = compile("bun.p95 <= node.p95*1.1", &["node", "bun"]).unwrap();
+
+        let outcome = compiled.evaluate(&[result("node", 50, 100, 150), result("bun", 50, 105, 150)]);
+        assert!(outcome.passed);
+        assert_eq!(outcome.checks.len(), 1);
+        assert_eq!(outcome.checks[0].actual, Some(105.0));
+        assert!((outcome.checks[0].limit.unwrap() - 110.0).abs() < 1e-9);
+
+        let outcome = compiled.evaluate(&[result("node", 50, 100, 150), result("bun", 50, 120, 150)]);
+        assert!(!outcome.passed);
+        assert!(outcome.breached());
+    }
+
+    #[test]
+    fn parses_units_and_quoted_targets() {
+        let compiled = compile("'node-18'.p95 < 0.2s", &["node-18"]).unwrap();
+        let outcome = compiled.evaluate(&[result("node-18", 50, 150, 250)]);
+        assert_eq!(outcome.checks[0].limit, Some(200.0));
+        assert!(outcome.passed);
+
+        assert!(compile("error_rate < 0.5%", &["a"]).is_ok());
+        assert!(compile("node-18.p95 < 1s", &["node-18"]).is_err());
+        assert!(compile("c.p95 < 1s", &["a"]).unwrap_err().contains("unknown target `c`"));
+        assert!(compile("p42 < 1s", &["a"]).unwrap_err().contains("unknown metric `p42`"));
+        assert!(compile("'a.p95 < 1s", &["a"]).is_err());
+    }
+
+    #[test]
+    fn missing_data_fails_without_breaching() {
+        let compiled = compile("p99 < 200ms", &["a", "b"]).unwrap();
+        let mut failed = result("b", 0, 0, 0);
+        failed.successful_requests = 0;
+
+        let outcome = compiled.evaluate(&[result("a", 3, 6, 9)]);
+        assert!(!outcome.passed);
+        assert!(!outcome.breached());
+        assert_eq!(outcome.checks[1].actual, None);
+
+        let outcome = compiled.evaluate(&[result("a", 3, 6, 9), failed]);
+        assert!(!outcome.passed);
+        assert!(!outcome.breached());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::request::PreparedRequest;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{client::conn::http1::SendRequest, header::HOST, Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use reqwest::{header::HeaderValue, Url};
+use std::{
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    rustls::{self, pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+use tracing::debug;
+
+/// Time spent in each phase of one request. The connection phases are `None`
+/// when the request reused a pooled connection, or (for `dns` and `tls`) when
+/// the phase does not apply to the target.
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseTimings {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+    /// From writing the request to receiving the response head.
+    pub ttfb: Duration,
+    /// From the response head to the end of the body.
+    pub body: Duration,
+}
+
+#[derive(Debug)]
+pub struct TimedResponse {
+    pub status: StatusCode,
+    pub phases: PhaseTimings,
+}
+
+#[derive(Debug)]
+pub enum TimedError {
+    InvalidUrl(String),
+    Dns(String),
+    Connect(std::io::Error),
+    Tls(std::io::Error),
+    Http(hyper::Error),
+    Timeout,
+}
+
+impl fmt::Display for TimedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimedError::InvalidUrl(e) => write!(f, "Builder: {}", e),
+            TimedError::Dns(e) => write!(f, "DNS: {}", e),
+            TimedError::Connect(e) => write!(f, "Connection: {}", e),
+            TimedError::Tls(e) => write!(f, "TLS: {}", e),
+            TimedError::Http(e) => write!(f, "Request: {}", e),
+            TimedError::Timeout => write!(f, "Timeout"),
+        }
+    }
+}
+
+type Sender = SendRequest<Full<Bytes>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PoolKey {
+    https: bool,
+    host: String,
+    port: u16,
+}
+
+/// Minimal HTTP/1.1 client that times DNS resolution, TCP connect, TLS
+/// handshake, time-to-first-byte and body download separately, which
+/// `reqwest` does not expose. Connections are kept alive and reused, so the
+/// connection phases are only recorded when a new connection is opened.
+#[derive(Clone)]
+pub struct TimedClient {
+    tls: TlsConnector,
+    idle: Arc<Mutex<Vec<(PoolKey, Sender)>>>,
+    max_idle: usize,
+    timeout: Duration,
+}
+
+impl TimedClient {
+    pub fn new(timeout: Duration, max_idle: usize) -> Self {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("ring provider supports the default protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        Self {
+            tls: TlsConnector::from(Arc::new(config)),
+            idle: Arc::new(Mutex::new(Vec::new())),
+            max_idle,
+            timeout,
+        }
+    }
+
+    pub async fn send(&self, request: &PreparedRequest) -> Result<TimedResponse, TimedError> {
+        tokio::time::timeout(self.timeout, self.send_inner(request))
+            .await
+            .unwrap_or(Err(TimedError::Timeout))
+    }
+
+    async fn send_inner(&self, request: &PreparedRequest) -> Result<TimedResponse, TimedError> {
+        let url = &request.url;
+        let key = PoolKey {
+            https: url.scheme() == "https",
+            host: url
+                .host_str()
+                .ok_or_else(|| TimedError::InvalidUrl(format!("{} has no host", url)))?
+                .to_string(),
+            port: url
+                .port_or_known_default()
+                .ok_or_else(|| TimedError::InvalidUrl(format!("{} has no port", url)))?,
+        };
+
+        let (mut sender, mut phases) = match self.checkout(&key).await {
+            Some(sender) => (sender, PhaseTimings::reused()),
+            None => self.connect(url, &key).await?,
+        };
+
+        let mut builder = Request::builder()
+            .method(request.method.clone())
+            .uri(origin_form(url));
+        if let Some(headers) = builder.headers_mut() {
+            headers.extend(request.headers.clone());
+            if !headers.contains_key(HOST) {
+                let host = match url.port() {
+                    Some(port) => format!("{}:{}", key.host, port),
+                    None => key.host.clone(),
+                };
+                if let Ok(value) = HeaderValue::from_str(&host) {
+                    headers.insert(HOST, value);
+                }
+            }
+        }
+        let http_request = builder
+            .body(Full::new(request.body.clone().unwrap_or_default()))
+            .map_err(|e| TimedError::InvalidUrl(e.to_string()))?;
+
+        let request_start = Instant::now();
+        let response = sender.send_request(http_request).await.map_err(TimedError::Http)?;
+        phases.ttfb = request_start.elapsed();
+
+        let status = response.status();
+        let body_start = Instant::now();
+        response.into_body().collect().await.map_err(TimedError::Http)?;
+        phases.body = body_start.elapsed();
+
+        self.checkin(key, sender);
+
+        Ok(TimedResponse { status, phases })
+    }
+
+    async fn checkout(&self, key: &PoolKey) -> Option<Sender> {
+        loop {
+            let mut sender = {
+                let mut idle = self.idle.lock().unwrap();
+                let index = idle.iter().position(|(k, _)| k == key)?;
+                idle.swap_remove(index).1
+            };
+
+            if sender.ready().await.is_ok() {
+                return Some(sender);
+            }
+        }
+    }
+
+    fn checkin(&self, key: PoolKey, sender: Sender) {
+        if sender.is_closed() {
+            return;
+        }
+
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_idle {
+            idle.push((key, sender));
+        }
+    }
+
+    async fn connect(
+        &self,
+        url: &Url,
+        key: &PoolKey,
+    ) -> Result<(Sender, PhaseTimings), TimedError> {
+        let mut phases = PhaseTimings::reused();
+
+        let addrs: Vec<SocketAddr> = match url.host() {
+            Some(url::Host::Ipv4(ip)) => vec![SocketAddr::from((ip, key.port))],
+            Some(url::Host::Ipv6(ip)) => vec![SocketAddr::from((ip, key.port))],
+            _ => {
+                let dns_start = Instant::now();
+                let addrs = tokio::net::lookup_host((key.host.as_str(), key.port))
+                    .await
+                    .map_err(|e| TimedError::Dns(e.to_string()))?
+                    .collect();
+                phases.dns = Some(dns_start.elapsed());
+                addrs
+            }
+        };
+
+        let connect_start = Instant::now();
+        let stream = connect_any(&addrs).await?;
+        let _ = stream.set_nodelay(true);
+        phases.connect = Some(connect_start.elapsed());
+
+        let sender = if key.https {
+            let server_name = match url.host() {
+                Some(url::Host::Ipv4(ip)) => ServerName::IpAddress(std::net::IpAddr::V4(ip).into()),
+                Some(url::Host::Ipv6(ip)) => ServerName::IpAddress(std::net::IpAddr::V6(ip).into()),
+                _ => ServerName::try_from(key.host.clone())
+                    .map_err(|e| TimedError::InvalidUrl(e.to_string()))?,
+            };
+
+            let tls_start = Instant::now();
+            let stream = self
+                .tls
+                .connect(server_name, stream)
+                .await
+                .map_err(TimedError::Tls)?;
+            phases.tls = Some(tls_start.elapsed());
+
+            handshake(stream).await?
+        } else {
+            handshake(stream).await?
+        };
+
+        Ok((sender, phases))
+    }
+}
+
+impl PhaseTimings {
+    fn reused() -> Self {
+        Self {
+            dns: None,
+            connect: None,
+            tls: None,
+            ttfb: Duration::ZERO,
+            body: Duration::ZERO,
+        }
+    }
+}
+
+async fn connect_any(addrs: &[SocketAddr]) -> Result<TcpStream, TimedError> {
+    let mut last_error = None;
+
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(TimedError::Connect(last_error.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses resolved")
+    })))
+}
+
+async fn handshake<S>(stream: S) -> Result<Sender, TimedError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+{
+    let (sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(TimedError::Http)?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("Timed client connection closed: {}", e);
+        }
+    });
+
+    Ok(sender)
+}
+
+/// Path and query of `url`, the request target for an HTTP/1.1 origin server.
+fn origin_form(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{comparison::error_rate_percent, load_tester::LoadTestResult};
+use serde::{Deserialize, Serialize};
+
+/// Repeats every target several times so run-to-run noise can be measured.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrialConfig {
+    /// Trials per target; 1 runs each target once, as without trials.
+    #[serde(default = "default_count")]
+    pub count: u32,
+    #[serde(default)]
+    pub order: TrialOrder,
+    #[serde(default)]
+    pub execution: TrialExecution,
+    /// Pause between consecutive rounds (concurrent) or trials (sequential).
+    #[serde(default)]
+    pub cooldown_seconds: u64,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+impl Default for TrialConfig {
+    fn default() -> Self {
+        Self {
+            count: default_count(),
+            order: TrialOrder::default(),
+            execution: TrialExecution::default(),
+            cooldown_seconds: 0,
+        }
+    }
+}
+
+/// Order of the targets within each round of trials.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrialOrder {
+    /// Targets in the order given, every round: A B A B ...
+    #[default]
+    Alternating,
+    /// Targets shuffled independently in every round.
+    Randomized,
+}
+
+/// Whether the targets of a round share the load generator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrialExecution {
+    /// All targets of a round run at the same time.
+    #[default]
+    Concurrent,
+    /// One trial at a time, so targets never compete for the generator.
+    Sequential,
+}
+
+impl TrialConfig {
+    pub fn enabled(&self) -> bool {
+        self.count > 1
+    }
+
+    /// Rounds of `(trial number, target index)` pairs in execution order.
+    /// Trial numbers start at 1.
+    pub fn schedule(&self, targets: usize) -> Vec<Vec<(u32, usize)>> {
+        (1..=self.count)
+            .map(|trial| {
+                let mut round: Vec<(u32, usize)> = (0..targets).map(|target| (trial, target)).collect();
+                if self.order == TrialOrder::Randomized {
+                    fastrand::shuffle(&mut round);
+                }
+                round
+            })
+            .collect()
+    }
+}
+
+/// Spread of one metric across a target's trials.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TrialStats {
+    pub mean: f64,
+    /// Sample standard deviation; 0 with a single trial.
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Standard deviation as a percentage of the mean.
+    pub cv_percent: f64,
+}
+
+impl TrialStats {
+    fn from_values(values: &[f64]) -> Self {
+        let n = values.len().max(1) as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = if values.len() > 1 {
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)
+        } else {
+            0.0
+        };
+        let std_dev = variance.sqrt();
+
+        Self {
+            mean,
+            std_dev,
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            cv_percent: if mean != 0.0 { std_dev / mean * 100.0 } else { 0.0 },
+        }
+    }
+}
+
+/// A target's results aggregated across its trials.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrialAggregate {
+    pub target: String,
+    pub trials: usize,
+    pub total_requests: u64,
+    pub requests_per_second: TrialStats,
+    pub avg_latency_ms: TrialStats,
+    pub p50_latency_ms: TrialStats,
+    pub p95_latency_ms: TrialStats,
+    pub p99_latency_ms: TrialStats,
+    pub error_rate_percent: TrialStats,
+}
+
+impl TrialAggregate {
+    /// One aggregate per target, in order of first appearance. Empty unless
+    /// the results come from trials.
+    pub fn from_results(results: &[LoadTestResult]) -> Vec<Self> {
+        if results.iter().all(|result| result.trial.is_none()) {
+            return Vec::new();
+        }
+
+        let mut targets: Vec<&str> = Vec::new();
+        for result in results {
+            if !targets.contains(&result.runtime.as_str()) {
+                targets.push(&result.runtime);
+            }
+        }
+
+        targets
+            .into_iter()
+            .map(|target| {
+                let trials: Vec<&LoadTestResult> =
+                    results.iter().filter(|result| result.runtime == target).collect();
+                let stats = |metric: fn(&LoadTestResult) -> f64| {
+                    TrialStats::from_values(&trials.iter().map(|r| metric(r)).collect::<Vec<_>>())
+                };
+
+                Self {
+                    target: target.to_string(),
+                    trials: trials.len(),
+                    total_requests: trials.iter().map(|r| r.total_requests).sum(),
+                    requests_per_second: stats(|r| r.requests_per_second),
+                    avg_latency_ms: stats(|r| r.avg_latency_ms),
+                    p50_latency_ms: stats(|r| r.p50_latency_ms),
+                    p95_latency_ms: stats(|r| r.p95_latency_ms),
+                    p99_latency_ms: stats(|r| r.p99_latency_ms),
+                    error_rate_percent: stats(error_rate_percent),
+                }
+            })
+            .collect()
+    }
+}